@@ -3,6 +3,14 @@ set -e
 
 echo "🚀 Deploying StreamPay to Conway Testnet..."
 
+# Token instantiation argument. There is no usable default: without an admin nobody can
+# configure the faucet, and without an allocation nobody can fund its reserve.
+if [ -z "$TOKEN_ARGUMENT" ]; then
+    echo "❌ TOKEN_ARGUMENT is not set. Pass the token's admins, minters and initial allocations, e.g."
+    echo "   TOKEN_ARGUMENT='{\"admins\": [\"User:0x123...\"], \"minters\": [\"User:0x123...\"], \"initial_allocations\": [{\"owner\": \"User:0x123...\", \"amount\": \"1000000.\"}]}' ./deploy.sh"
+    exit 1
+fi
+
 # Build everything first
 echo "📦 Building applications..."
 cargo build --release --target wasm32-unknown-unknown
@@ -12,7 +20,7 @@ echo "💰 Deploying Token app..."
 TOKEN_OUTPUT=$(linera publish-and-create \
   target/wasm32-unknown-unknown/release/token_contract.wasm \
   target/wasm32-unknown-unknown/release/token_service.wasm \
-  --json-argument "{}" 2>&1)
+  --json-argument "$TOKEN_ARGUMENT" 2>&1)
 
 TOKEN_APP_ID=$(echo "$TOKEN_OUTPUT" | grep -oP 'ApplicationId\(\K[^)]+' | head -1)
 
@@ -27,17 +35,24 @@ echo "✅ Token App ID: $TOKEN_APP_ID"
 # Create stream params
 cat > stream_params.json <<EOF
 {
-  "token_app": "$TOKEN_APP_ID"
+  "token_app": "$TOKEN_APP_ID",
+  "max_fee_bps": ${MAX_FEE_BPS:-100}
 }
 EOF
 
+# Stream instantiation argument, e.g.
+# '{"admins": ["User:0x123..."], "allowed_tokens": ["<other token app id>"],
+#   "fee": {"bps": 25, "basis": "Withdrawals", "treasury": "User:0x123...", "max_fee": null}}'
+STREAM_ARGUMENT=${STREAM_ARGUMENT:-"{}"}
+
 echo ""
 echo "💸 Deploying Stream app..."
 STREAM_OUTPUT=$(linera publish-and-create \
   target/wasm32-unknown-unknown/release/stream_contract.wasm \
   target/wasm32-unknown-unknown/release/stream_service.wasm \
   --json-parameters-file stream_params.json \
-  --json-argument "{}" 2>&1)
+  --json-argument "$STREAM_ARGUMENT" \
+  --required-application-ids "$TOKEN_APP_ID" 2>&1)
 
 STREAM_APP_ID=$(echo "$STREAM_OUTPUT" | grep -oP 'ApplicationId\(\K[^)]+' | head -1)
 
@@ -3,14 +3,19 @@
 mod state;
 
 use linera_sdk::{
-    linera_base_types::{AccountOwner, Amount, Timestamp, WithContractAbi},
+    linera_base_types::{
+        Account, AccountOwner, Amount, ApplicationId, StreamName, Timestamp, WithContractAbi,
+    },
     views::{RootView, View},
     Contract, ContractRuntime,
 };
 use stream::{
-    Stream, StreamAbi, StreamMessage, StreamOperation, StreamParameters,
-    StreamResponse, StreamStatus,
+    AdminAction, AdminActionKind, Dispute, FeeBasis, Metering, Milestone, Stream, StreamAbi,
+    StreamActivity, StreamEvent, StreamInstantiationArgument, StreamMessage, StreamOperation,
+    StreamParameters, StreamResponse, StreamStatus, StreamUpdate, Subscription, SubscriptionStatus,
+    MAX_BPS, STREAM_EVENTS,
 };
+use token::{TokenAbi, TokenOperation, TokenResponse};
 
 use self::state::StreamState;
 
@@ -28,8 +33,8 @@ impl WithContractAbi for StreamContract {
 impl Contract for StreamContract {
     type Message = StreamMessage;
     type Parameters = StreamParameters;
-    type InstantiationArgument = ();
-    type EventValue = ();
+    type InstantiationArgument = StreamInstantiationArgument;
+    type EventValue = StreamEvent;
 
     async fn load(runtime: ContractRuntime<Self>) -> Self {
         let state = StreamState::load(runtime.root_view_storage_context())
@@ -38,9 +43,39 @@ impl Contract for StreamContract {
         StreamContract { state, runtime }
     }
 
-    async fn instantiate(&mut self, _argument: Self::InstantiationArgument) {
-        let _ = self.runtime.application_parameters();
+    async fn instantiate(&mut self, argument: Self::InstantiationArgument) {
+        let parameters = self.runtime.application_parameters();
         *self.state.next_stream_id.get_mut() = 1;
+        *self.state.next_subscription_id.get_mut() = 1;
+
+        self.state
+            .allowed_tokens
+            .insert(&parameters.token_app.forget_abi())
+            .unwrap();
+        for token in argument.allowed_tokens {
+            self.state.allowed_tokens.insert(&token).unwrap();
+        }
+        // Whoever creates the application administers it unless admins are named
+        let admins = if argument.admins.is_empty() {
+            self.runtime.authenticated_signer().into_iter().collect()
+        } else {
+            argument.admins
+        };
+        for admin in admins {
+            self.state.admins.insert(&admin).unwrap();
+        }
+
+        assert!(
+            parameters.max_fee_bps <= MAX_BPS,
+            "max_fee_bps can't exceed {} bps",
+            MAX_BPS
+        );
+        assert!(
+            argument.fee.bps <= parameters.max_fee_bps,
+            "Fee exceeds the maximum of {} bps",
+            parameters.max_fee_bps
+        );
+        self.state.fee_config.set(argument.fee);
     }
 
     async fn execute_operation(&mut self, operation: Self::Operation) -> Self::Response {
@@ -51,12 +86,34 @@ impl Contract for StreamContract {
                 recipient,
                 rate_per_second,
                 duration_seconds,
+                token,
+                native,
+                arbiter,
+                irrevocable,
+                clawback_seconds,
             } => {
                 let sender = match self.runtime.authenticated_signer() {
                     Some(signer) => signer.into(),
                     None => return StreamResponse::Error("Not authenticated".to_string()),
                 };
 
+                if arbiter.is_some_and(|arbiter| arbiter == sender || arbiter == recipient) {
+                    return StreamResponse::Error("Arbiter must be a third party".to_string());
+                }
+
+                // A dispute freezes accrual, which would let the sender halt an irrevocable
+                // stream by disputing it
+                let irrevocable = irrevocable.unwrap_or(false);
+                if irrevocable && arbiter.is_some() {
+                    return StreamResponse::Error(
+                        "Irrevocable streams can't have an arbiter".to_string(),
+                    );
+                }
+
+                if *self.state.creation_paused.get() {
+                    return StreamResponse::Error("Stream creation is paused".to_string());
+                }
+
                 let rate = match rate_per_second.parse::<u128>() {
                     Ok(r) => Amount::from_attos(r),
                     Err(_) => return StreamResponse::Error("Invalid rate".to_string()),
@@ -66,14 +123,30 @@ impl Contract for StreamContract {
                     return StreamResponse::Error("Rate must be > 0".to_string());
                 }
 
+                let irrevocable = irrevocable.unwrap_or(false);
+                if irrevocable && clawback_seconds.is_some() {
+                    return StreamResponse::Error(
+                        "Irrevocable streams can't have a clawback window".to_string(),
+                    );
+                }
+
+                let token = match self.resolve_funding(token, native).await {
+                    Ok(token) => token,
+                    Err(error) => return StreamResponse::Error(error),
+                };
+
                 let end_time = duration_seconds.map(|dur| {
-                    let micros = dur * 1_000_000;
+                    let micros = dur.saturating_mul(1_000_000);
+                    Timestamp::from(current_time.micros().saturating_add(micros))
+                });
+
+                let clawback_until = clawback_seconds.map(|seconds| {
+                    let micros = seconds.saturating_mul(1_000_000);
                     Timestamp::from(current_time.micros().saturating_add(micros))
                 });
 
-                let stream_id = *self.state.next_stream_id.get();
                 let stream = Stream {
-                    id: stream_id,
+                    id: *self.state.next_stream_id.get(),
                     sender,
                     recipient,
                     rate_per_second: rate,
@@ -82,16 +155,280 @@ impl Contract for StreamContract {
                     paused_at: None,
                     total_deposited: Amount::ZERO,
                     total_withdrawn: Amount::ZERO,
+                    total_refunded: Amount::ZERO,
+                    status: StreamStatus::Active,
+                    checkpoint_time: current_time,
+                    streamed_at_checkpoint: Amount::ZERO,
+                    token,
+                    metering: None,
+                    milestones: Vec::new(),
+                    arbiter,
+                    dispute: None,
+                    irrevocable,
+                    clawback_until,
+                };
+
+                StreamResponse::StreamId(self.open_stream(stream).await)
+            }
+
+            StreamOperation::CreateMeteredStream {
+                recipient,
+                meter,
+                price_per_unit,
+                period_seconds,
+                cap_per_period,
+                duration_seconds,
+                token,
+                native,
+                arbiter,
+            } => {
+                let sender = match self.runtime.authenticated_signer() {
+                    Some(signer) => signer.into(),
+                    None => return StreamResponse::Error("Not authenticated".to_string()),
+                };
+
+                if arbiter.is_some_and(|arbiter| arbiter == sender || arbiter == recipient) {
+                    return StreamResponse::Error("Arbiter must be a third party".to_string());
+                }
+
+                if *self.state.creation_paused.get() {
+                    return StreamResponse::Error("Stream creation is paused".to_string());
+                }
+
+                let price_per_unit = match price_per_unit.parse::<u128>() {
+                    Ok(p) => Amount::from_attos(p),
+                    Err(_) => return StreamResponse::Error("Invalid price".to_string()),
+                };
+
+                if price_per_unit == Amount::ZERO {
+                    return StreamResponse::Error("Price must be > 0".to_string());
+                }
+
+                if period_seconds == 0 {
+                    return StreamResponse::Error("Period must be > 0".to_string());
+                }
+
+                let cap_per_period = match cap_per_period.map(|cap| cap.parse::<u128>()).transpose()
+                {
+                    Ok(cap) => cap.map(Amount::from_attos),
+                    Err(_) => return StreamResponse::Error("Invalid cap".to_string()),
+                };
+
+                let token = match self.resolve_funding(token, native).await {
+                    Ok(token) => token,
+                    Err(error) => return StreamResponse::Error(error),
+                };
+
+                let end_time = duration_seconds.map(|dur| {
+                    let micros = dur.saturating_mul(1_000_000);
+                    Timestamp::from(current_time.micros().saturating_add(micros))
+                });
+
+                let stream = Stream {
+                    id: *self.state.next_stream_id.get(),
+                    sender,
+                    recipient,
+                    rate_per_second: Amount::ZERO,
+                    start_time: current_time,
+                    end_time,
+                    paused_at: None,
+                    total_deposited: Amount::ZERO,
+                    total_withdrawn: Amount::ZERO,
+                    total_refunded: Amount::ZERO,
                     status: StreamStatus::Active,
+                    checkpoint_time: current_time,
+                    streamed_at_checkpoint: Amount::ZERO,
+                    token,
+                    metering: Some(Metering {
+                        meter,
+                        price_per_unit,
+                        period_seconds,
+                        cap_per_period,
+                        total_units: 0,
+                        period_start: current_time,
+                        billed_in_period: Amount::ZERO,
+                    }),
+                    milestones: Vec::new(),
+                    arbiter,
+                    dispute: None,
+                    irrevocable: false,
+                    clawback_until: None,
+                };
+
+                StreamResponse::StreamId(self.open_stream(stream).await)
+            }
+
+            StreamOperation::ReportUsage { stream_id, units } => {
+                let meter = match self.runtime.authenticated_signer() {
+                    Some(s) => s.into(),
+                    None => return StreamResponse::Error("Not authenticated".to_string()),
                 };
 
+                let mut stream = match self.state.streams.get(&stream_id).await.unwrap() {
+                    Some(s) => s,
+                    None => return StreamResponse::Error("Stream not found".to_string()),
+                };
+
+                if stream
+                    .metering
+                    .as_ref()
+                    .is_none_or(|metering| metering.meter != meter)
+                {
+                    return StreamResponse::Error(
+                        "Only the stream's meter can report usage".to_string(),
+                    );
+                }
+
+                self.settle_completion(&mut stream, current_time).await;
+                if stream.status != StreamStatus::Active {
+                    return StreamResponse::Error("Stream not active".to_string());
+                }
+
+                let amount = stream
+                    .metering
+                    .as_mut()
+                    .expect("Metered stream lost its metering")
+                    .bill(units, current_time);
+                stream.streamed_at_checkpoint =
+                    stream.streamed_at_checkpoint.saturating_add(amount);
+                stream.checkpoint_time = current_time;
+                self.record(
+                    &stream,
+                    StreamEvent::UsageReported {
+                        stream_id,
+                        units,
+                        amount,
+                    },
+                )
+                .await;
                 self.state.streams.insert(&stream_id, stream).unwrap();
-                *self.state.next_stream_id.get_mut() = stream_id + 1;
 
-                self.state.add_stream_to_sender(sender, stream_id).await;
-                self.state.add_stream_to_recipient(recipient, stream_id).await;
+                StreamResponse::Amount(amount)
+            }
+
+            StreamOperation::CreateMilestoneStream {
+                recipient,
+                milestones,
+                token,
+                native,
+                arbiter,
+            } => {
+                let sender = match self.runtime.authenticated_signer() {
+                    Some(signer) => signer.into(),
+                    None => return StreamResponse::Error("Not authenticated".to_string()),
+                };
+
+                if arbiter.is_some_and(|arbiter| arbiter == sender || arbiter == recipient) {
+                    return StreamResponse::Error("Arbiter must be a third party".to_string());
+                }
+
+                if *self.state.creation_paused.get() {
+                    return StreamResponse::Error("Stream creation is paused".to_string());
+                }
+
+                if milestones.is_empty() {
+                    return StreamResponse::Error("At least one milestone is required".to_string());
+                }
+
+                let mut parsed = Vec::with_capacity(milestones.len());
+                let mut milestone_total = Amount::ZERO;
+                for milestone in milestones {
+                    let amount = match milestone.amount.parse::<u128>() {
+                        Ok(a) if a > 0 => Amount::from_attos(a),
+                        _ => {
+                            return StreamResponse::Error(format!(
+                                "Invalid amount for milestone {}",
+                                milestone.name
+                            ))
+                        }
+                    };
+                    if milestone_total.try_add_assign(amount).is_err() {
+                        return StreamResponse::Error("Milestone amounts overflow".to_string());
+                    }
+                    parsed.push(Milestone {
+                        name: milestone.name,
+                        amount,
+                        deadline: milestone.deadline,
+                        release_seconds: milestone.release_seconds.unwrap_or(0),
+                        approved_at: None,
+                    });
+                }
+
+                let token = match self.resolve_funding(token, native).await {
+                    Ok(token) => token,
+                    Err(error) => return StreamResponse::Error(error),
+                };
+
+                let stream = Stream {
+                    id: *self.state.next_stream_id.get(),
+                    sender,
+                    recipient,
+                    rate_per_second: Amount::ZERO,
+                    start_time: current_time,
+                    end_time: None,
+                    paused_at: None,
+                    total_deposited: Amount::ZERO,
+                    total_withdrawn: Amount::ZERO,
+                    total_refunded: Amount::ZERO,
+                    status: StreamStatus::Active,
+                    checkpoint_time: current_time,
+                    streamed_at_checkpoint: Amount::ZERO,
+                    token,
+                    metering: None,
+                    milestones: parsed,
+                    arbiter,
+                    dispute: None,
+                    irrevocable: false,
+                    clawback_until: None,
+                };
 
-                StreamResponse::StreamId(stream_id)
+                StreamResponse::StreamId(self.open_stream(stream).await)
+            }
+
+            StreamOperation::ApproveMilestone {
+                stream_id,
+                milestone,
+            } => {
+                let sender = match self.runtime.authenticated_signer() {
+                    Some(s) => s.into(),
+                    None => return StreamResponse::Error("Not authenticated".to_string()),
+                };
+
+                let mut stream = match self.state.streams.get(&stream_id).await.unwrap() {
+                    Some(s) => s,
+                    None => return StreamResponse::Error("Stream not found".to_string()),
+                };
+
+                if stream.sender != sender {
+                    return StreamResponse::Error("Only sender can approve milestones".to_string());
+                }
+
+                if !matches!(stream.status, StreamStatus::Active | StreamStatus::Paused) {
+                    return StreamResponse::Error("Stream already ended".to_string());
+                }
+
+                let Some(entry) = stream.milestones.get_mut(milestone as usize) else {
+                    return StreamResponse::Error("Milestone not found".to_string());
+                };
+
+                if entry.unlocked_at(current_time).is_some() {
+                    return StreamResponse::Error("Milestone already unlocked".to_string());
+                }
+
+                entry.approved_at = Some(current_time);
+                let name = entry.name.clone();
+                self.record(
+                    &stream,
+                    StreamEvent::MilestoneApproved {
+                        stream_id,
+                        milestone,
+                        name,
+                    },
+                )
+                .await;
+                self.state.streams.insert(&stream_id, stream).unwrap();
+
+                StreamResponse::Ok
             }
 
             StreamOperation::PauseStream { stream_id } => {
@@ -109,12 +446,21 @@ impl Contract for StreamContract {
                     return StreamResponse::Error("Only sender can pause".to_string());
                 }
 
+                if stream.irrevocable {
+                    return StreamResponse::Error(
+                        "Irrevocable streams can't be paused".to_string(),
+                    );
+                }
+
+                self.settle_completion(&mut stream, current_time).await;
                 if stream.status != StreamStatus::Active {
                     return StreamResponse::Error("Stream not active".to_string());
                 }
 
                 stream.status = StreamStatus::Paused;
                 stream.paused_at = Some(current_time);
+                self.record(&stream, StreamEvent::Paused { stream_id })
+                    .await;
                 self.state.streams.insert(&stream_id, stream).unwrap();
 
                 StreamResponse::Ok
@@ -139,8 +485,18 @@ impl Contract for StreamContract {
                     return StreamResponse::Error("Stream not paused".to_string());
                 }
 
+                // Restart accrual from now so the paused interval isn't paid out
+                stream.streamed_at_checkpoint = stream.accrued_at(current_time);
+                stream.checkpoint_time = current_time;
+                if let Some(paused_at) = stream.paused_at {
+                    for milestone in &mut stream.milestones {
+                        milestone.resume(paused_at, current_time);
+                    }
+                }
                 stream.status = StreamStatus::Active;
                 stream.paused_at = None;
+                self.record(&stream, StreamEvent::Resumed { stream_id })
+                    .await;
                 self.state.streams.insert(&stream_id, stream).unwrap();
 
                 StreamResponse::Ok
@@ -152,6 +508,10 @@ impl Contract for StreamContract {
                     None => return StreamResponse::Error("Not authenticated".to_string()),
                 };
 
+                if *self.state.withdrawals_paused.get() {
+                    return StreamResponse::Error("Withdrawals are paused".to_string());
+                }
+
                 let mut stream = match self.state.streams.get(&stream_id).await.unwrap() {
                     Some(s) => s,
                     None => return StreamResponse::Error("Stream not found".to_string()),
@@ -161,9 +521,131 @@ impl Contract for StreamContract {
                     return StreamResponse::Error("Only sender can stop".to_string());
                 }
 
+                self.settle_completion(&mut stream, current_time).await;
+
+                // Once an irrevocable stream has run its course, stopping only returns the
+                // deposit it never streamed
+                if stream.irrevocable && stream.status != StreamStatus::Completed {
+                    return StreamResponse::Error(
+                        "Irrevocable streams can't be stopped".to_string(),
+                    );
+                }
+
+                if stream.status == StreamStatus::Disputed {
+                    return StreamResponse::Error("Stream is under dispute".to_string());
+                }
+
+                if matches!(
+                    stream.status,
+                    StreamStatus::Stopped | StreamStatus::Resolved | StreamStatus::ClawedBack
+                ) {
+                    return StreamResponse::Error("Stream already ended".to_string());
+                }
+
+                // Freeze what was streamed so far and hand the rest of the deposit back. A
+                // completed stream may still hold deposit it never streamed, which only
+                // stopping it returns.
+                let ended_at = stream.effective_time(current_time);
+                let streamed = self.state.calculate_streamed_amount(&stream, current_time);
+                let refunded = stream.total_deposited.saturating_sub(streamed);
+                if stream.status == StreamStatus::Completed && refunded == Amount::ZERO {
+                    return StreamResponse::Error("Stream already ended".to_string());
+                }
+                if refunded > Amount::ZERO {
+                    if let Err(error) = self.pay_out(
+                        stream.token,
+                        sender,
+                        refunded,
+                        format!("Refund from stream {}", stream_id),
+                    ) {
+                        return StreamResponse::Error(format!("Refund failed: {}", error));
+                    }
+                }
+
+                stream.streamed_at_checkpoint = streamed;
+                stream.checkpoint_time = ended_at;
                 stream.status = StreamStatus::Stopped;
+                stream.end_time = Some(ended_at);
+                stream.total_refunded = stream.total_refunded.saturating_add(refunded);
+                self.record(
+                    &stream,
+                    StreamEvent::Stopped {
+                        stream_id,
+                        refunded,
+                    },
+                )
+                .await;
+                let token = stream.token;
+                self.state.streams.insert(&stream_id, stream).unwrap();
+                self.state
+                    .update_token_totals(token, |totals| {
+                        totals.total_refunded = totals.total_refunded.saturating_add(refunded);
+                        totals.escrowed = totals.escrowed.saturating_sub(refunded);
+                    })
+                    .await;
+
+                StreamResponse::Ok
+            }
+
+            StreamOperation::ClawbackStream { stream_id } => {
+                let sender = match self.runtime.authenticated_signer() {
+                    Some(s) => s.into(),
+                    None => return StreamResponse::Error("Not authenticated".to_string()),
+                };
+
+                if *self.state.withdrawals_paused.get() {
+                    return StreamResponse::Error("Withdrawals are paused".to_string());
+                }
+
+                let mut stream = match self.state.streams.get(&stream_id).await.unwrap() {
+                    Some(s) => s,
+                    None => return StreamResponse::Error("Stream not found".to_string()),
+                };
+
+                if stream.sender != sender {
+                    return StreamResponse::Error("Only sender can claw back".to_string());
+                }
+
+                if !stream.in_clawback_window(current_time) {
+                    return StreamResponse::Error("Clawback window has closed".to_string());
+                }
+
+                if !matches!(stream.status, StreamStatus::Active | StreamStatus::Paused) {
+                    return StreamResponse::Error("Stream already ended".to_string());
+                }
+
+                // Everything the recipient hasn't withdrawn goes back, streamed or not
+                let refunded = stream.escrowed();
+                if refunded > Amount::ZERO {
+                    if let Err(error) = self.pay_out(
+                        stream.token,
+                        sender,
+                        refunded,
+                        format!("Clawback of stream {}", stream_id),
+                    ) {
+                        return StreamResponse::Error(format!("Refund failed: {}", error));
+                    }
+                }
+
+                stream.status = StreamStatus::ClawedBack;
                 stream.end_time = Some(current_time);
+                stream.total_refunded = stream.total_refunded.saturating_add(refunded);
+                self.record(
+                    &stream,
+                    StreamEvent::ClawedBack {
+                        stream_id,
+                        refunded,
+                    },
+                )
+                .await;
+                let token = stream.token;
                 self.state.streams.insert(&stream_id, stream).unwrap();
+                self.state
+                    .update_token_totals(token, |totals| {
+                        totals.total_refunded = totals.total_refunded.saturating_add(refunded);
+                        totals.escrowed = totals.escrowed.saturating_sub(refunded);
+                    })
+                    .await;
 
                 StreamResponse::Ok
             }
@@ -183,6 +665,15 @@ impl Contract for StreamContract {
                     return StreamResponse::Error("Only recipient can withdraw".to_string());
                 }
 
+                if *self.state.withdrawals_paused.get() {
+                    return StreamResponse::Error("Withdrawals are paused".to_string());
+                }
+
+                self.settle_completion(&mut stream, current_time).await;
+                if stream.status == StreamStatus::Disputed {
+                    return StreamResponse::Error("Stream is under dispute".to_string());
+                }
+
                 let available = self.state.calculate_earned_amount(&stream, current_time);
 
                 let withdraw_amount = if let Some(amt_str) = amount {
@@ -201,11 +692,41 @@ impl Contract for StreamContract {
                     ));
                 }
 
+                let fee = self
+                    .state
+                    .fee_config
+                    .get()
+                    .fee_for(FeeBasis::Withdrawals, withdraw_amount);
+                let received = withdraw_amount.saturating_sub(fee);
+                if let Err(error) = self.pay_out(
+                    stream.token,
+                    recipient,
+                    received,
+                    format!("Withdrawal from stream {}", stream_id),
+                ) {
+                    return StreamResponse::Error(format!("Withdrawal failed: {}", error));
+                }
+
                 stream.total_withdrawn = stream.total_withdrawn.saturating_add(withdraw_amount);
+                self.record(
+                    &stream,
+                    StreamEvent::Withdrawn {
+                        stream_id,
+                        recipient,
+                        amount: withdraw_amount,
+                        fee,
+                    },
+                )
+                .await;
+                let token = stream.token;
                 self.state.streams.insert(&stream_id, stream).unwrap();
-
-                // Note: In production, you'd call token app to transfer funds here
-                // For now, we'll just track the withdrawal
+                self.state
+                    .update_token_totals(token, |totals| {
+                        totals.total_withdrawn = totals.total_withdrawn.saturating_add(received);
+                        totals.total_fees = totals.total_fees.saturating_add(fee);
+                        totals.escrowed = totals.escrowed.saturating_sub(withdraw_amount);
+                    })
+                    .await;
 
                 StreamResponse::Amount(withdraw_amount)
             }
@@ -225,13 +746,719 @@ impl Contract for StreamContract {
                     return StreamResponse::Error("Only sender can top up".to_string());
                 }
 
+                self.settle_completion(&mut stream, current_time).await;
+                if stream.status == StreamStatus::Disputed {
+                    return StreamResponse::Error("Stream is under dispute".to_string());
+                }
+
+                if matches!(
+                    stream.status,
+                    StreamStatus::Completed
+                        | StreamStatus::Stopped
+                        | StreamStatus::Resolved
+                        | StreamStatus::ClawedBack
+                ) {
+                    return StreamResponse::Error("Stream already ended".to_string());
+                }
+
                 let top_up_amount = match amount.parse::<u128>() {
                     Ok(a) => Amount::from_attos(a),
                     Err(_) => return StreamResponse::Error("Invalid amount".to_string()),
                 };
+                let fee = self
+                    .state
+                    .fee_config
+                    .get()
+                    .fee_for(FeeBasis::Deposits, top_up_amount);
+                let credited = top_up_amount.saturating_sub(fee);
+
+                // A milestone stream never pays out more than its milestones add up to
+                if !stream.milestones.is_empty() {
+                    let milestone_total = stream
+                        .milestones
+                        .iter()
+                        .fold(Amount::ZERO, |total, milestone| {
+                            total.saturating_add(milestone.amount)
+                        });
+                    let unfunded = milestone_total.saturating_sub(stream.total_deposited);
+                    if credited > unfunded {
+                        return StreamResponse::Error(format!(
+                            "Top-up exceeds the milestones. Unfunded: {}, Requested: {}",
+                            unfunded, credited
+                        ));
+                    }
+                }
+
+                if let Err(error) = self.deposit(
+                    stream.token,
+                    sender,
+                    top_up_amount,
+                    format!("Deposit into stream {}", stream_id),
+                ) {
+                    return StreamResponse::Error(format!("Deposit failed: {}", error));
+                }
+
+                stream.total_deposited = stream.total_deposited.saturating_add(credited);
+                self.record(
+                    &stream,
+                    StreamEvent::ToppedUp {
+                        stream_id,
+                        sender,
+                        amount: credited,
+                        fee,
+                    },
+                )
+                .await;
+                let token = stream.token;
+                self.state.streams.insert(&stream_id, stream).unwrap();
+                self.state
+                    .update_token_totals(token, |totals| {
+                        totals.total_deposited = totals.total_deposited.saturating_add(credited);
+                        totals.total_fees = totals.total_fees.saturating_add(fee);
+                        totals.escrowed = totals.escrowed.saturating_add(credited);
+                    })
+                    .await;
+
+                StreamResponse::Ok
+            }
+
+            StreamOperation::UpdateStreamRate {
+                stream_id,
+                rate_per_second,
+            } => {
+                let sender = match self.runtime.authenticated_signer() {
+                    Some(s) => s.into(),
+                    None => return StreamResponse::Error("Not authenticated".to_string()),
+                };
+
+                let mut stream = match self.state.streams.get(&stream_id).await.unwrap() {
+                    Some(s) => s,
+                    None => return StreamResponse::Error("Stream not found".to_string()),
+                };
+
+                if stream.sender != sender {
+                    return StreamResponse::Error("Only sender can change the rate".to_string());
+                }
+
+                if stream.metering.is_some() {
+                    return StreamResponse::Error(
+                        "Metered streams are priced per unit".to_string(),
+                    );
+                }
+
+                if !stream.milestones.is_empty() {
+                    return StreamResponse::Error(
+                        "Milestone streams pay per milestone".to_string(),
+                    );
+                }
+
+                let new_rate = match rate_per_second.parse::<u128>() {
+                    Ok(r) => Amount::from_attos(r),
+                    Err(_) => return StreamResponse::Error("Invalid rate".to_string()),
+                };
+
+                if new_rate == Amount::ZERO {
+                    return StreamResponse::Error("Rate must be > 0".to_string());
+                }
+
+                if stream.irrevocable && new_rate < stream.rate_per_second {
+                    return StreamResponse::Error(
+                        "Irrevocable streams can't be slowed down".to_string(),
+                    );
+                }
+
+                self.settle_completion(&mut stream, current_time).await;
+                if !matches!(stream.status, StreamStatus::Active | StreamStatus::Paused) {
+                    return StreamResponse::Error("Stream already ended".to_string());
+                }
+
+                // Bank what accrued at the old rate before switching
+                stream.streamed_at_checkpoint = stream.accrued_at(current_time);
+                stream.checkpoint_time = current_time;
+                let old_rate = stream.rate_per_second;
+                stream.rate_per_second = new_rate;
+                self.record(
+                    &stream,
+                    StreamEvent::RateChanged {
+                        stream_id,
+                        old_rate,
+                        new_rate,
+                    },
+                )
+                .await;
+                self.state.streams.insert(&stream_id, stream).unwrap();
+
+                StreamResponse::Ok
+            }
+
+            StreamOperation::RaiseDispute { stream_id, reason } => {
+                let caller = match self.runtime.authenticated_signer() {
+                    Some(s) => s.into(),
+                    None => return StreamResponse::Error("Not authenticated".to_string()),
+                };
+
+                let mut stream = match self.state.streams.get(&stream_id).await.unwrap() {
+                    Some(s) => s,
+                    None => return StreamResponse::Error("Stream not found".to_string()),
+                };
+
+                if stream.sender != caller && stream.recipient != caller {
+                    return StreamResponse::Error(
+                        "Only the sender or recipient can raise a dispute".to_string(),
+                    );
+                }
+
+                if stream.arbiter.is_none() {
+                    return StreamResponse::Error("Stream has no arbiter".to_string());
+                }
+
+                self.settle_completion(&mut stream, current_time).await;
+                if matches!(
+                    stream.status,
+                    StreamStatus::Disputed | StreamStatus::Resolved
+                ) {
+                    return StreamResponse::Error("Stream already disputed".to_string());
+                }
+
+                if stream.escrowed() == Amount::ZERO {
+                    return StreamResponse::Error("Nothing in escrow to dispute".to_string());
+                }
+
+                // Freeze accrual where it stands, as stopping does
+                let frozen_at = self.state.effective_time(&stream, current_time);
+                stream.streamed_at_checkpoint = stream.accrued_at(current_time);
+                stream.checkpoint_time = frozen_at;
+                stream.end_time = Some(frozen_at);
+                stream.status = StreamStatus::Disputed;
+                stream.dispute = Some(Dispute {
+                    raised_by: caller,
+                    raised_at: current_time,
+                    reason,
+                    resolved_at: None,
+                    recipient_amount: None,
+                    sender_amount: None,
+                });
+                self.record(
+                    &stream,
+                    StreamEvent::DisputeRaised {
+                        stream_id,
+                        raised_by: caller,
+                    },
+                )
+                .await;
+                self.state.streams.insert(&stream_id, stream).unwrap();
+
+                StreamResponse::Ok
+            }
+
+            StreamOperation::ResolveDispute {
+                stream_id,
+                recipient_amount,
+            } => {
+                let arbiter = match self.runtime.authenticated_signer() {
+                    Some(s) => s.into(),
+                    None => return StreamResponse::Error("Not authenticated".to_string()),
+                };
+
+                if *self.state.withdrawals_paused.get() {
+                    return StreamResponse::Error("Withdrawals are paused".to_string());
+                }
+
+                let mut stream = match self.state.streams.get(&stream_id).await.unwrap() {
+                    Some(s) => s,
+                    None => return StreamResponse::Error("Stream not found".to_string()),
+                };
+
+                if stream.arbiter != Some(arbiter) {
+                    return StreamResponse::Error("Only the arbiter can resolve".to_string());
+                }
+
+                if stream.status != StreamStatus::Disputed {
+                    return StreamResponse::Error("Stream not disputed".to_string());
+                }
+
+                let recipient_amount = match recipient_amount.parse::<u128>() {
+                    Ok(a) => Amount::from_attos(a),
+                    Err(_) => return StreamResponse::Error("Invalid amount".to_string()),
+                };
 
-                stream.total_deposited = stream.total_deposited.saturating_add(top_up_amount);
+                let escrowed = stream.escrowed();
+                if recipient_amount > escrowed {
+                    return StreamResponse::Error(format!(
+                        "Ruling exceeds escrow. Escrowed: {}, Requested: {}",
+                        escrowed, recipient_amount
+                    ));
+                }
+                let sender_amount = escrowed.saturating_sub(recipient_amount);
+
+                // Once one side is paid the ruling can't be returned as an error, or the
+                // stream would stay disputed and the arbiter could pay that side twice
+                let mut paid = false;
+                for (owner, amount, memo) in [
+                    (stream.recipient, recipient_amount, "Ruling on stream"),
+                    (stream.sender, sender_amount, "Refund by ruling on stream"),
+                ] {
+                    if amount == Amount::ZERO {
+                        continue;
+                    }
+                    if let Err(error) = self.pay_out(
+                        stream.token,
+                        owner,
+                        amount,
+                        format!("{} {}", memo, stream_id),
+                    ) {
+                        if paid {
+                            panic!("Failed to pay out ruling: {}", error);
+                        }
+                        return StreamResponse::Error(format!("Payout failed: {}", error));
+                    }
+                    paid = true;
+                }
+
+                stream.total_withdrawn = stream.total_withdrawn.saturating_add(recipient_amount);
+                stream.total_refunded = stream.total_refunded.saturating_add(sender_amount);
+                stream.status = StreamStatus::Resolved;
+                if let Some(dispute) = stream.dispute.as_mut() {
+                    dispute.resolved_at = Some(current_time);
+                    dispute.recipient_amount = Some(recipient_amount);
+                    dispute.sender_amount = Some(sender_amount);
+                }
+                self.record(
+                    &stream,
+                    StreamEvent::DisputeResolved {
+                        stream_id,
+                        recipient_amount,
+                        sender_amount,
+                    },
+                )
+                .await;
+                let token = stream.token;
                 self.state.streams.insert(&stream_id, stream).unwrap();
+                self.state
+                    .update_token_totals(token, |totals| {
+                        totals.total_withdrawn =
+                            totals.total_withdrawn.saturating_add(recipient_amount);
+                        totals.total_refunded = totals.total_refunded.saturating_add(sender_amount);
+                        totals.escrowed = totals.escrowed.saturating_sub(escrowed);
+                    })
+                    .await;
+
+                StreamResponse::Ok
+            }
+
+            StreamOperation::AllowToken { token } => {
+                let admin = match self.check_admin().await {
+                    Ok(admin) => admin,
+                    Err(error) => return StreamResponse::Error(error),
+                };
+
+                self.state.allowed_tokens.insert(&token).unwrap();
+                self.state.admin_log.push(AdminAction {
+                    token: Some(token),
+                    ..AdminAction::new(admin, AdminActionKind::AllowToken, current_time)
+                });
+
+                StreamResponse::Ok
+            }
+
+            StreamOperation::DisallowToken { token } => {
+                let admin = match self.check_admin().await {
+                    Ok(admin) => admin,
+                    Err(error) => return StreamResponse::Error(error),
+                };
+
+                self.state.allowed_tokens.remove(&token).unwrap();
+                self.state.admin_log.push(AdminAction {
+                    token: Some(token),
+                    ..AdminAction::new(admin, AdminActionKind::DisallowToken, current_time)
+                });
+
+                StreamResponse::Ok
+            }
+
+            StreamOperation::SetFeeConfig { config } => {
+                let admin = match self.check_admin().await {
+                    Ok(admin) => admin,
+                    Err(error) => return StreamResponse::Error(error),
+                };
+
+                let max_fee_bps = self.runtime.application_parameters().max_fee_bps;
+                if config.bps > max_fee_bps.min(MAX_BPS) {
+                    return StreamResponse::Error(format!(
+                        "Fee exceeds the maximum of {} bps",
+                        max_fee_bps
+                    ));
+                }
+
+                self.state.fee_config.set(config.clone());
+                self.state.admin_log.push(AdminAction {
+                    fee: Some(config),
+                    ..AdminAction::new(admin, AdminActionKind::SetFeeConfig, current_time)
+                });
+
+                StreamResponse::Ok
+            }
+
+            StreamOperation::SetEmergencyPause {
+                creation,
+                withdrawals,
+            } => {
+                let admin = match self.check_admin().await {
+                    Ok(admin) => admin,
+                    Err(error) => return StreamResponse::Error(error),
+                };
+
+                if let Some(paused) = creation {
+                    self.state.creation_paused.set(paused);
+                    self.state.admin_log.push(AdminAction {
+                        paused: Some(paused),
+                        ..AdminAction::new(admin, AdminActionKind::PauseCreation, current_time)
+                    });
+                }
+                if let Some(paused) = withdrawals {
+                    self.state.withdrawals_paused.set(paused);
+                    self.state.admin_log.push(AdminAction {
+                        paused: Some(paused),
+                        ..AdminAction::new(admin, AdminActionKind::PauseWithdrawals, current_time)
+                    });
+                }
+
+                StreamResponse::Ok
+            }
+
+            StreamOperation::AddAdmin { account } => {
+                let admin = match self.check_admin().await {
+                    Ok(admin) => admin,
+                    Err(error) => return StreamResponse::Error(error),
+                };
+
+                self.state.admins.insert(&account).unwrap();
+                self.state.admin_log.push(AdminAction {
+                    account: Some(account),
+                    ..AdminAction::new(admin, AdminActionKind::AddAdmin, current_time)
+                });
+
+                StreamResponse::Ok
+            }
+
+            StreamOperation::RemoveAdmin { account } => {
+                let admin = match self.check_admin().await {
+                    Ok(admin) => admin,
+                    Err(error) => return StreamResponse::Error(error),
+                };
+
+                if !self.state.is_admin(&account).await {
+                    return StreamResponse::Error("Account is not an admin".to_string());
+                }
+
+                if self.state.admins.count().await.unwrap() == 1 {
+                    return StreamResponse::Error("Can't remove the last admin".to_string());
+                }
+
+                self.state.admins.remove(&account).unwrap();
+                self.state.admin_log.push(AdminAction {
+                    account: Some(account),
+                    ..AdminAction::new(admin, AdminActionKind::RemoveAdmin, current_time)
+                });
+
+                StreamResponse::Ok
+            }
+
+            StreamOperation::TransferAdmin { new_admin } => {
+                let admin = match self.check_admin().await {
+                    Ok(admin) => admin,
+                    Err(error) => return StreamResponse::Error(error),
+                };
+
+                if new_admin == admin {
+                    return StreamResponse::Error("Already an admin".to_string());
+                }
+
+                self.state.admins.remove(&admin).unwrap();
+                self.state.admins.insert(&new_admin).unwrap();
+                self.state.admin_log.push(AdminAction {
+                    account: Some(new_admin),
+                    ..AdminAction::new(admin, AdminActionKind::TransferAdmin, current_time)
+                });
+
+                StreamResponse::Ok
+            }
+
+            StreamOperation::ClaimFees { token, native } => {
+                let caller = match self.runtime.authenticated_signer() {
+                    Some(s) => s.into(),
+                    None => return StreamResponse::Error("Not authenticated".to_string()),
+                };
+
+                if *self.state.withdrawals_paused.get() {
+                    return StreamResponse::Error("Withdrawals are paused".to_string());
+                }
+
+                let Some(treasury) = self.state.fee_config.get().treasury else {
+                    return StreamResponse::Error("No treasury is set".to_string());
+                };
+                if caller != treasury {
+                    return StreamResponse::Error("Only the treasury can claim fees".to_string());
+                }
+
+                let token = match (token, native.unwrap_or(false)) {
+                    (Some(token), _) => Some(token),
+                    (None, true) => None,
+                    (None, false) => {
+                        Some(self.runtime.application_parameters().token_app.forget_abi())
+                    }
+                };
+                let totals = self.state.token_totals_of(token).await;
+                let unclaimed = totals.total_fees.saturating_sub(totals.fees_claimed);
+                if unclaimed == Amount::ZERO {
+                    return StreamResponse::Error("No fees to claim".to_string());
+                }
+
+                if let Err(error) =
+                    self.pay_out(token, treasury, unclaimed, "Protocol fees".to_string())
+                {
+                    return StreamResponse::Error(format!("Claim failed: {}", error));
+                }
+
+                self.state
+                    .update_token_totals(token, |totals| {
+                        totals.fees_claimed = totals.fees_claimed.saturating_add(unclaimed);
+                    })
+                    .await;
+
+                StreamResponse::Amount(unclaimed)
+            }
+
+            StreamOperation::CreateSubscription {
+                merchant,
+                amount_per_period,
+                period_seconds,
+                cap,
+                token,
+            } => {
+                let subscriber = match self.runtime.authenticated_signer() {
+                    Some(s) => s.into(),
+                    None => return StreamResponse::Error("Not authenticated".to_string()),
+                };
+
+                if *self.state.creation_paused.get() {
+                    return StreamResponse::Error("Subscription creation is paused".to_string());
+                }
+
+                let amount_per_period = match amount_per_period.parse::<u128>() {
+                    Ok(a) => Amount::from_attos(a),
+                    Err(_) => return StreamResponse::Error("Invalid amount".to_string()),
+                };
+
+                if amount_per_period == Amount::ZERO {
+                    return StreamResponse::Error("Amount must be > 0".to_string());
+                }
+
+                if period_seconds == 0 {
+                    return StreamResponse::Error("Period must be > 0".to_string());
+                }
+
+                let cap = match cap.map(|cap| cap.parse::<u128>()).transpose() {
+                    Ok(cap) => cap.map(Amount::from_attos),
+                    Err(_) => return StreamResponse::Error("Invalid cap".to_string()),
+                };
+
+                if cap.is_some_and(|cap| cap < amount_per_period) {
+                    return StreamResponse::Error(
+                        "Cap must cover at least one payment".to_string(),
+                    );
+                }
+
+                let token = token.unwrap_or_else(|| {
+                    self.runtime.application_parameters().token_app.forget_abi()
+                });
+                if !self.state.is_token_allowed(&token).await {
+                    return StreamResponse::Error("Token not allowed".to_string());
+                }
+
+                let subscription_id = *self.state.next_subscription_id.get();
+                let subscription = Subscription {
+                    id: subscription_id,
+                    subscriber,
+                    merchant,
+                    token,
+                    amount_per_period,
+                    period_seconds,
+                    cap,
+                    start_time: current_time,
+                    next_period: 0,
+                    next_charge_at: current_time,
+                    total_charged: Amount::ZERO,
+                    missed_payments: 0,
+                    status: SubscriptionStatus::Active,
+                    ended_at: None,
+                    cancelled_by: None,
+                };
+
+                self.state
+                    .subscriptions
+                    .insert(&subscription_id, subscription)
+                    .unwrap();
+                *self.state.next_subscription_id.get_mut() = subscription_id + 1;
+
+                self.state
+                    .add_subscription_to_subscriber(subscriber, subscription_id)
+                    .await;
+                self.state
+                    .add_subscription_to_merchant(merchant, subscription_id)
+                    .await;
+                self.runtime.emit(
+                    StreamName::from(STREAM_EVENTS),
+                    &StreamEvent::SubscriptionCreated {
+                        subscription_id,
+                        subscriber,
+                        merchant,
+                        amount_per_period,
+                        period_seconds,
+                    },
+                );
+
+                StreamResponse::SubscriptionId(subscription_id)
+            }
+
+            StreamOperation::Charge { subscription_id } => {
+                let merchant = match self.runtime.authenticated_signer() {
+                    Some(s) => s.into(),
+                    None => return StreamResponse::Error("Not authenticated".to_string()),
+                };
+
+                if *self.state.withdrawals_paused.get() {
+                    return StreamResponse::Error("Withdrawals are paused".to_string());
+                }
+
+                let mut subscription = match self
+                    .state
+                    .subscriptions
+                    .get(&subscription_id)
+                    .await
+                    .unwrap()
+                {
+                    Some(s) => s,
+                    None => return StreamResponse::Error("Subscription not found".to_string()),
+                };
+
+                if subscription.merchant != merchant {
+                    return StreamResponse::Error("Only the merchant can charge".to_string());
+                }
+
+                if subscription.status != SubscriptionStatus::Active {
+                    return StreamResponse::Error("Subscription not active".to_string());
+                }
+
+                let period = subscription.period_at(current_time);
+                if period < subscription.next_period {
+                    return StreamResponse::Error(format!(
+                        "Payment not due until {}",
+                        subscription.next_charge_at
+                    ));
+                }
+
+                // Periods that ended uncharged are missed for good, even if this charge fails
+                if period > subscription.next_period {
+                    let periods = period - subscription.next_period;
+                    subscription.missed_payments += periods;
+                    subscription.next_period = period;
+                    subscription.next_charge_at = subscription.period_start(period);
+                    self.state
+                        .subscriptions
+                        .insert(&subscription_id, subscription.clone())
+                        .unwrap();
+                    self.runtime.emit(
+                        StreamName::from(STREAM_EVENTS),
+                        &StreamEvent::SubscriptionPaymentsMissed {
+                            subscription_id,
+                            periods,
+                        },
+                    );
+                }
+
+                let amount = subscription.amount_per_period;
+                let escrow = self.escrow_account();
+                if let Err(error) = self.call_token(
+                    subscription.token,
+                    TokenOperation::TransferFrom {
+                        spender: escrow,
+                        owner: subscription.subscriber,
+                        amount,
+                        target_owner: merchant,
+                        memo: Some(format!(
+                            "Subscription {} period {}",
+                            subscription_id, period
+                        )),
+                    },
+                ) {
+                    return StreamResponse::Error(format!("Charge failed: {}", error));
+                }
+
+                subscription.total_charged = subscription.total_charged.saturating_add(amount);
+                subscription.next_period = period + 1;
+                subscription.next_charge_at = subscription.period_start(period + 1);
+                if !subscription.can_charge_again() {
+                    subscription.status = SubscriptionStatus::Completed;
+                    subscription.ended_at = Some(current_time);
+                }
+                self.state
+                    .subscriptions
+                    .insert(&subscription_id, subscription)
+                    .unwrap();
+                self.runtime.emit(
+                    StreamName::from(STREAM_EVENTS),
+                    &StreamEvent::SubscriptionCharged {
+                        subscription_id,
+                        period,
+                        amount,
+                    },
+                );
+
+                StreamResponse::Amount(amount)
+            }
+
+            StreamOperation::CancelSubscription { subscription_id } => {
+                let caller = match self.runtime.authenticated_signer() {
+                    Some(s) => s.into(),
+                    None => return StreamResponse::Error("Not authenticated".to_string()),
+                };
+
+                let mut subscription = match self
+                    .state
+                    .subscriptions
+                    .get(&subscription_id)
+                    .await
+                    .unwrap()
+                {
+                    Some(s) => s,
+                    None => return StreamResponse::Error("Subscription not found".to_string()),
+                };
+
+                if subscription.subscriber != caller && subscription.merchant != caller {
+                    return StreamResponse::Error(
+                        "Only the subscriber or merchant can cancel".to_string(),
+                    );
+                }
+
+                if subscription.status != SubscriptionStatus::Active {
+                    return StreamResponse::Error("Subscription not active".to_string());
+                }
+
+                subscription.status = SubscriptionStatus::Cancelled;
+                subscription.ended_at = Some(current_time);
+                subscription.cancelled_by = Some(caller);
+                self.state
+                    .subscriptions
+                    .insert(&subscription_id, subscription)
+                    .unwrap();
+                self.runtime.emit(
+                    StreamName::from(STREAM_EVENTS),
+                    &StreamEvent::SubscriptionCancelled {
+                        subscription_id,
+                        cancelled_by: caller,
+                    },
+                );
 
                 StreamResponse::Ok
             }
@@ -244,3 +1471,232 @@ impl Contract for StreamContract {
         self.state.save().await.expect("Failed to save state");
     }
 }
+
+impl StreamContract {
+    /// Emit a lifecycle event, append it to the update feed that clients poll and to the
+    /// stream's own history
+    async fn record(&mut self, stream: &Stream, event: StreamEvent) {
+        let timestamp = self.runtime.system_time();
+        let kind = event
+            .kind()
+            .expect("Only stream events are recorded against a stream");
+        let actor = match event {
+            StreamEvent::Completed { .. } => None,
+            _ => self.runtime.authenticated_signer(),
+        };
+        let position = self.state.updates.count() as u64;
+        self.state.updates.push(StreamUpdate {
+            stream_id: stream.id,
+            sender: stream.sender,
+            recipient: stream.recipient,
+            kind,
+            timestamp,
+        });
+        self.state
+            .updates_by_stream
+            .load_entry_mut(&stream.id)
+            .await
+            .unwrap()
+            .push(position);
+        let mut accounts = vec![stream.sender, stream.recipient];
+        accounts.dedup();
+        for account in accounts {
+            self.state
+                .updates_by_account
+                .load_entry_mut(&account)
+                .await
+                .unwrap()
+                .push(position);
+        }
+        self.state
+            .history
+            .load_entry_mut(&stream.id)
+            .await
+            .unwrap()
+            .push(StreamActivity {
+                kind,
+                actor,
+                amount: event.amount(),
+                rate_per_second: event.rate_per_second(),
+                timestamp,
+            });
+        self.runtime.emit(StreamName::from(STREAM_EVENTS), &event);
+    }
+
+    /// Check the funding choice of a new stream: a token on the allowlist, defaulting to the
+    /// `token_app` parameter, or the native balance
+    async fn resolve_funding(
+        &mut self,
+        token: Option<ApplicationId>,
+        native: Option<bool>,
+    ) -> Result<Option<ApplicationId>, String> {
+        if !native.unwrap_or(false) {
+            let token = token
+                .unwrap_or_else(|| self.runtime.application_parameters().token_app.forget_abi());
+            if !self.state.is_token_allowed(&token).await {
+                return Err("Token not allowed".to_string());
+            }
+            return Ok(Some(token));
+        }
+        if token.is_some() {
+            return Err(
+                "A stream can't be funded by both a token and the native balance".to_string(),
+            );
+        }
+        Ok(None)
+    }
+
+    /// Store a new stream under the next ID and index it by sender and recipient
+    async fn open_stream(&mut self, stream: Stream) -> u64 {
+        let stream_id = stream.id;
+        let (sender, recipient, token) = (stream.sender, stream.recipient, stream.token);
+        self.record(
+            &stream,
+            StreamEvent::Created {
+                stream_id,
+                sender,
+                recipient,
+                rate_per_second: stream.rate_per_second,
+                end_time: stream.end_time,
+                token,
+            },
+        )
+        .await;
+        self.state.streams.insert(&stream_id, stream).unwrap();
+        *self.state.next_stream_id.get_mut() = stream_id + 1;
+        self.state
+            .update_token_totals(token, |totals| totals.stream_count += 1)
+            .await;
+
+        self.state.add_stream_to_sender(sender, stream_id).await;
+        self.state
+            .add_stream_to_recipient(recipient, stream_id)
+            .await;
+        stream_id
+    }
+
+    /// Mark an active stream whose end time has passed as completed, persisting the change
+    /// even if the calling operation then fails
+    async fn settle_completion(&mut self, stream: &mut Stream, current_time: Timestamp) {
+        if stream.status == StreamStatus::Active
+            && self.state.should_complete_stream(stream, current_time)
+        {
+            stream.status = StreamStatus::Completed;
+            self.record(
+                stream,
+                StreamEvent::Completed {
+                    stream_id: stream.id,
+                },
+            )
+            .await;
+            self.state
+                .streams
+                .insert(&stream.id, stream.clone())
+                .unwrap();
+        }
+    }
+
+    /// The stream application's own account, which holds the escrow of every stream
+    fn escrow_account(&mut self) -> AccountOwner {
+        AccountOwner::from(self.runtime.application_id().forget_abi())
+    }
+
+    /// Pull `amount` of `token` from `owner` into escrow, using the allowance `owner` gave
+    /// the stream application. Native deposits move the signer's own balance.
+    fn deposit(
+        &mut self,
+        token: Option<ApplicationId>,
+        owner: AccountOwner,
+        amount: Amount,
+        memo: String,
+    ) -> Result<(), String> {
+        let escrow = self.escrow_account();
+        let Some(token) = token else {
+            return self.transfer_native(owner, escrow, amount);
+        };
+        self.call_token(
+            token,
+            TokenOperation::TransferFrom {
+                spender: escrow,
+                owner,
+                amount,
+                target_owner: escrow,
+                memo: Some(memo),
+            },
+        )
+    }
+
+    /// Send `amount` of `token`, or of the native token if `None`, from escrow to `owner` on
+    /// this chain
+    fn pay_out(
+        &mut self,
+        token: Option<ApplicationId>,
+        owner: AccountOwner,
+        amount: Amount,
+        memo: String,
+    ) -> Result<(), String> {
+        let escrow = self.escrow_account();
+        let Some(token) = token else {
+            return self.transfer_native(escrow, owner, amount);
+        };
+        let target_account = Account {
+            chain_id: self.runtime.chain_id(),
+            owner,
+        };
+        self.call_token(
+            token,
+            TokenOperation::Transfer {
+                owner: escrow,
+                amount,
+                target_account,
+                memo: Some(memo),
+            },
+        )
+    }
+
+    /// Check that the operation was signed by an admin and return the admin
+    async fn check_admin(&mut self) -> Result<AccountOwner, String> {
+        let Some(admin) = self.runtime.authenticated_signer() else {
+            return Err("Not authenticated".to_string());
+        };
+        if !self.state.is_admin(&admin).await {
+            return Err("Only admins can do this".to_string());
+        }
+        Ok(admin)
+    }
+
+    /// Move native tokens between two accounts on this chain. The runtime aborts the block
+    /// on an overdraft, so the balance is checked first.
+    fn transfer_native(
+        &mut self,
+        source: AccountOwner,
+        target: AccountOwner,
+        amount: Amount,
+    ) -> Result<(), String> {
+        if self.runtime.owner_balance(source) < amount {
+            return Err("Insufficient native balance".to_string());
+        }
+        let destination = Account {
+            chain_id: self.runtime.chain_id(),
+            owner: target,
+        };
+        self.runtime.transfer(source, destination, amount);
+        Ok(())
+    }
+
+    /// Run a token operation that moves funds, which only succeeds with `TokenResponse::Ok`
+    fn call_token(
+        &mut self,
+        token: ApplicationId,
+        operation: TokenOperation,
+    ) -> Result<(), String> {
+        match self
+            .runtime
+            .call_application(true, token.with_abi::<TokenAbi>(), &operation)
+        {
+            TokenResponse::Ok => Ok(()),
+            TokenResponse::Error(error) => Err(error.to_string()),
+            response => Err(format!("Unexpected token response: {:?}", response)),
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use async_graphql::{Request, Response, Enum, SimpleObject};
+use std::cmp::Ordering;
+
+use async_graphql::{connection::CursorType, Enum, InputObject, Request, Response, SimpleObject};
 use linera_sdk::{
     graphql::GraphQLMutationRoot,
     linera_base_types::{AccountOwner, Amount, ApplicationId, ContractAbi, ServiceAbi, Timestamp},
@@ -24,6 +26,57 @@ pub enum StreamOperation {
         recipient: AccountOwner,
         rate_per_second: String, // Amount as string for GraphQL
         duration_seconds: Option<u64>,
+        /// Token application funding the stream, which must be on the allowlist. Defaults to
+        /// the `token_app` parameter.
+        token: Option<ApplicationId>,
+        /// Fund the stream from the chain's native balance instead of a token application.
+        native: Option<bool>,
+        /// Account that rules on disputes. Without one, the stream can't be disputed.
+        arbiter: Option<AccountOwner>,
+        /// Guarantee the stream to the recipient: it can't be paused, stopped or slowed down.
+        /// Can't be combined with `arbiter`.
+        irrevocable: Option<bool>,
+        /// Grace period after creation during which the sender can reverse the stream with
+        /// `ClawbackStream`.
+        clawback_seconds: Option<u64>,
+    },
+
+    /// Open a stream that pays per unit of usage reported by `meter` instead of per second
+    CreateMeteredStream {
+        recipient: AccountOwner,
+        meter: AccountOwner,
+        price_per_unit: String, // Amount as string for GraphQL
+        /// Length of the window `cap_per_period` applies to.
+        period_seconds: u64,
+        /// Most the stream pays per period. Usage beyond it isn't billed.
+        cap_per_period: Option<String>,
+        duration_seconds: Option<u64>,
+        token: Option<ApplicationId>,
+        native: Option<bool>,
+        arbiter: Option<AccountOwner>,
+    },
+
+    /// Bill usage on a metered stream (meter only)
+    ReportUsage {
+        stream_id: u64,
+        units: u64,
+    },
+
+    /// Open a stream that pays out a list of milestones as the sender approves them. Top-ups
+    /// can fund it up to the sum of the milestone amounts.
+    CreateMilestoneStream {
+        recipient: AccountOwner,
+        milestones: Vec<MilestoneInput>,
+        token: Option<ApplicationId>,
+        native: Option<bool>,
+        arbiter: Option<AccountOwner>,
+    },
+
+    /// Unlock a milestone ahead of its deadline (sender only)
+    ApproveMilestone {
+        stream_id: u64,
+        /// Position of the milestone in the stream's list.
+        milestone: u32,
     },
 
     PauseStream {
@@ -34,25 +87,126 @@ pub enum StreamOperation {
         stream_id: u64,
     },
 
+    /// Stop the stream and refund the unstreamed deposit to the sender (sender only). This
+    /// also returns what a completed stream never streamed. Fails while the token refuses to
+    /// pay the sender, e.g. if they are frozen or denylisted, and the stream keeps running
+    /// until the token lifts that.
     StopStream {
         stream_id: u64,
     },
 
+    /// Reverse a stream created by mistake, refunding everything not yet withdrawn. Only
+    /// possible while its clawback window is open (sender only).
+    ClawbackStream {
+        stream_id: u64,
+    },
+
     WithdrawFromStream {
         stream_id: u64,
         amount: Option<String>, // Amount as string for GraphQL
     },
 
+    /// Deposit into the stream's escrow. For token-funded streams, the sender must first
+    /// approve the stream application's account as a spender on the stream's token.
     TopUpStream {
         stream_id: u64,
         amount: String,
     },
+
+    UpdateStreamRate {
+        stream_id: u64,
+        rate_per_second: String, // Amount as string for GraphQL
+    },
+
+    /// Freeze the stream's escrow until the arbiter rules (sender or recipient)
+    RaiseDispute {
+        stream_id: u64,
+        reason: Option<String>,
+    },
+
+    /// Settle a dispute by paying `recipient_amount` of the remaining escrow to the recipient
+    /// and refunding the rest to the sender (arbiter only)
+    ResolveDispute {
+        stream_id: u64,
+        recipient_amount: String, // Amount as string for GraphQL
+    },
+
+    /// Let new streams be funded with a token application (admin only)
+    AllowToken {
+        token: ApplicationId,
+    },
+
+    /// Stop new streams from using a token application (admin only). Existing streams keep
+    /// working.
+    DisallowToken {
+        token: ApplicationId,
+    },
+
+    /// Change the protocol fee (admin only). The rate can't exceed the `max_fee_bps`
+    /// parameter.
+    SetFeeConfig {
+        config: FeeConfig,
+    },
+
+    /// Pay the protocol fees collected in one token to the treasury (treasury only). Claims
+    /// `token`, the native token with `native: true`, or else the `token_app` parameter.
+    ClaimFees {
+        token: Option<ApplicationId>,
+        native: Option<bool>,
+    },
+
+    /// Halt or restart stream creation and/or withdrawals in an emergency (admin only).
+    /// Unset flags are left as they are. Pausing creation also blocks new subscriptions, and
+    /// pausing withdrawals blocks every payment out of escrow and subscription charges.
+    SetEmergencyPause {
+        creation: Option<bool>,
+        withdrawals: Option<bool>,
+    },
+
+    /// Grant admin rights to another account (admin only)
+    AddAdmin {
+        account: AccountOwner,
+    },
+
+    /// Revoke an account's admin rights (admin only). The last admin can't be removed.
+    RemoveAdmin {
+        account: AccountOwner,
+    },
+
+    /// Hand the caller's admin rights over to another account (admin only)
+    TransferAdmin {
+        new_admin: AccountOwner,
+    },
+
+    /// Authorize `merchant` to pull a fixed amount every period. The subscriber must approve
+    /// the stream application's account as a spender on the token.
+    CreateSubscription {
+        merchant: AccountOwner,
+        amount_per_period: String, // Amount as string for GraphQL
+        period_seconds: u64,
+        /// Most the merchant may pull in total. Unlimited if unset.
+        cap: Option<String>,
+        /// Token application to pay with, which must be on the allowlist. Defaults to the
+        /// `token_app` parameter.
+        token: Option<ApplicationId>,
+    },
+
+    /// Pull the payment for the current period (merchant only)
+    Charge {
+        subscription_id: u64,
+    },
+
+    /// End a subscription (subscriber or merchant)
+    CancelSubscription {
+        subscription_id: u64,
+    },
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub enum StreamResponse {
     Ok,
     StreamId(u64),
+    SubscriptionId(u64),
     Amount(Amount),
     Error(String),
 }
@@ -68,7 +222,444 @@ pub struct Stream {
     pub paused_at: Option<Timestamp>,
     pub total_deposited: Amount,
     pub total_withdrawn: Amount,
+    /// Unstreamed deposit returned to the sender when the stream was stopped.
+    pub total_refunded: Amount,
     pub status: StreamStatus,
+    /// When `rate_per_second` took effect: the start, the last rate change or the last resume.
+    pub checkpoint_time: Timestamp,
+    /// Amount streamed before `checkpoint_time`.
+    pub streamed_at_checkpoint: Amount,
+    /// Token application holding the stream's escrow, or `None` if the stream is funded
+    /// from the chain's native balance.
+    pub token: Option<ApplicationId>,
+    /// Set for streams that pay for reported usage. Their rate is zero.
+    pub metering: Option<Metering>,
+    /// Non-empty for streams that pay per milestone. Their rate is zero.
+    pub milestones: Vec<Milestone>,
+    pub arbiter: Option<AccountOwner>,
+    pub dispute: Option<Dispute>,
+    /// The sender can't pause, stop or lower the rate of the stream.
+    pub irrevocable: bool,
+    /// End of the window in which the sender can claw the stream back.
+    pub clawback_until: Option<Timestamp>,
+}
+
+impl Stream {
+    /// Deposits the stream application still holds for this stream
+    pub fn escrowed(&self) -> Amount {
+        self.total_deposited
+            .saturating_sub(self.total_withdrawn)
+            .saturating_sub(self.total_refunded)
+    }
+
+    /// Whether the sender can still claw the stream back at `time`
+    pub fn in_clawback_window(&self, time: Timestamp) -> bool {
+        self.clawback_until.is_some_and(|until| time < until)
+    }
+}
+
+/// A stream's clawback window as of the query time.
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct ClawbackWindow {
+    pub until: Timestamp,
+    pub open: bool,
+    pub seconds_remaining: u64,
+}
+
+/// A dispute over a stream's escrow and, once resolved, the arbiter's ruling.
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct Dispute {
+    pub raised_by: AccountOwner,
+    pub raised_at: Timestamp,
+    pub reason: Option<String>,
+    pub resolved_at: Option<Timestamp>,
+    /// Paid to the recipient by the ruling.
+    pub recipient_amount: Option<Amount>,
+    /// Refunded to the sender by the ruling.
+    pub sender_amount: Option<Amount>,
+}
+
+/// A tranche of a milestone stream, released once the sender approves it or its deadline
+/// passes.
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct Milestone {
+    pub name: String,
+    pub amount: Amount,
+    /// Unlocks automatically at this time if not approved before.
+    pub deadline: Option<Timestamp>,
+    /// How long the amount streams for after unlocking. Zero releases it at once.
+    pub release_seconds: u64,
+    pub approved_at: Option<Timestamp>,
+}
+
+impl Milestone {
+    /// When the milestone unlocked, if it had by `time`
+    pub fn unlocked_at(&self, time: Timestamp) -> Option<Timestamp> {
+        let approved_at = self.approved_at.filter(|approved_at| *approved_at <= time);
+        let deadline = self.deadline.filter(|deadline| *deadline <= time);
+        match (approved_at, deadline) {
+            (Some(approved_at), Some(deadline)) => Some(approved_at.min(deadline)),
+            (approved_at, deadline) => approved_at.or(deadline),
+        }
+    }
+
+    /// How much of the milestone has been released by `time`
+    pub fn released_at(&self, time: Timestamp) -> Amount {
+        let Some(unlocked_at) = self.unlocked_at(time) else {
+            return Amount::ZERO;
+        };
+        let elapsed = time.delta_since(unlocked_at).as_micros() / 1_000_000;
+        if elapsed >= self.release_seconds {
+            return self.amount;
+        }
+        // Split the division so `amount * elapsed` can't overflow
+        let (amount, elapsed) = (u128::from(self.amount), u128::from(elapsed));
+        let release_seconds = u128::from(self.release_seconds);
+        let released = amount / release_seconds * elapsed
+            + amount % release_seconds * elapsed / release_seconds;
+        Amount::from_attos(released)
+    }
+
+    /// Move the milestone's clock past a pause from `paused_at` to `resumed_at`, so it
+    /// neither unlocks nor releases anything while the stream is paused. Approvals given
+    /// during the pause take effect on resume.
+    pub fn resume(&mut self, paused_at: Timestamp, resumed_at: Timestamp) {
+        let paused_micros = resumed_at.delta_since(paused_at).as_micros();
+        let shift = |time: Timestamp| Timestamp::from(time.micros().saturating_add(paused_micros));
+        self.deadline = self.deadline.map(shift);
+        self.approved_at = self.approved_at.map(|approved_at| {
+            if approved_at <= paused_at {
+                shift(approved_at)
+            } else {
+                resumed_at
+            }
+        });
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, InputObject)]
+pub struct MilestoneInput {
+    pub name: String,
+    pub amount: String, // Amount as string for GraphQL
+    pub deadline: Option<Timestamp>,
+    pub release_seconds: Option<u64>,
+}
+
+/// A milestone as of the query time.
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct MilestoneProgress {
+    pub index: u32,
+    pub name: String,
+    pub amount: Amount,
+    pub unlocked_at: Option<Timestamp>,
+    pub released: Amount,
+}
+
+/// Usage pricing of a metered stream. Billed usage is added to the stream's
+/// `streamed_at_checkpoint`, so it is bounded by the deposit like time-based accrual.
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct Metering {
+    /// Account allowed to report usage.
+    pub meter: AccountOwner,
+    pub price_per_unit: Amount,
+    pub period_seconds: u64,
+    pub cap_per_period: Option<Amount>,
+    /// All units reported, including any beyond the cap.
+    pub total_units: u64,
+    /// Start of the current cap window.
+    pub period_start: Timestamp,
+    pub billed_in_period: Amount,
+}
+
+impl Metering {
+    /// Record `units` of usage at `now`, opening a new cap window if the current one has
+    /// ended, and return the amount billed after the cap
+    pub fn bill(&mut self, units: u64, now: Timestamp) -> Amount {
+        let period_micros = self.period_seconds.saturating_mul(1_000_000);
+        let periods = now.delta_since(self.period_start).as_micros() / period_micros;
+        if periods > 0 {
+            let offset = periods.saturating_mul(period_micros);
+            self.period_start = Timestamp::from(self.period_start.micros().saturating_add(offset));
+            self.billed_in_period = Amount::ZERO;
+        }
+
+        let cost = self.price_per_unit.saturating_mul(u128::from(units));
+        let billed = match self.cap_per_period {
+            Some(cap) => cost.min(cap.saturating_sub(self.billed_in_period)),
+            None => cost,
+        };
+        self.billed_in_period = self.billed_in_period.saturating_add(billed);
+        self.total_units = self.total_units.saturating_add(units);
+        billed
+    }
+}
+
+impl Stream {
+    /// How much the stream has paid out in total by `current_time`, withdrawn or not.
+    ///
+    /// Never exceeds the deposit: a stream that runs dry stops paying until it is topped up,
+    /// and the top-up then covers the shortfall.
+    pub fn streamed_at(&self, current_time: Timestamp) -> Amount {
+        self.accrued_at(current_time).min(self.total_deposited)
+    }
+
+    /// How much the stream has accrued by `current_time`, before the cap at the deposit.
+    ///
+    /// Checkpoints store this rather than the capped amount, so a shortfall accrued before a
+    /// rate change or a pause is still owed once the stream is topped up.
+    pub fn accrued_at(&self, current_time: Timestamp) -> Amount {
+        // The arbiter's ruling or the clawback settled everything the recipient gets
+        if matches!(
+            self.status,
+            StreamStatus::Resolved | StreamStatus::ClawedBack
+        ) {
+            return self.total_withdrawn;
+        }
+
+        let effective_time = self.effective_time(current_time);
+
+        if !self.milestones.is_empty() {
+            return self
+                .milestones
+                .iter()
+                .fold(Amount::ZERO, |total, milestone| {
+                    total.saturating_add(milestone.released_at(effective_time))
+                });
+        }
+
+        // Nothing accrues before the current rate took effect
+        if effective_time <= self.checkpoint_time {
+            return self.streamed_at_checkpoint;
+        }
+
+        let elapsed = effective_time.delta_since(self.checkpoint_time).as_micros();
+
+        let seconds_elapsed = (elapsed / 1_000_000) as u128;
+
+        // Total earned = earned at earlier rates + rate * seconds since the checkpoint
+        self.streamed_at_checkpoint
+            .saturating_add(self.rate_per_second.saturating_mul(seconds_elapsed))
+    }
+
+    /// The time the stream has accrued up to: now, or when it was paused or ended
+    pub fn effective_time(&self, current_time: Timestamp) -> Timestamp {
+        match self.status {
+            StreamStatus::Active => self
+                .end_time
+                .map_or(current_time, |end_time| current_time.min(end_time)),
+            StreamStatus::Paused => self.paused_at.unwrap_or(current_time),
+            StreamStatus::Completed
+            | StreamStatus::Stopped
+            | StreamStatus::Disputed
+            | StreamStatus::Resolved
+            | StreamStatus::ClawedBack => self.end_time.unwrap_or(current_time),
+        }
+    }
+}
+
+/// Value that has moved through escrow in one token application, or in the native token.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, SimpleObject)]
+pub struct TokenTotals {
+    /// `None` for the chain's native token.
+    pub token: Option<ApplicationId>,
+    /// Number of streams created with this token.
+    pub stream_count: u64,
+    pub total_deposited: Amount,
+    pub total_withdrawn: Amount,
+    pub total_refunded: Amount,
+    /// Protocol fees charged. They stay with the stream application until the treasury
+    /// claims them.
+    pub total_fees: Amount,
+    /// Protocol fees the treasury has claimed.
+    pub fees_claimed: Amount,
+    /// Held by the stream application for its streams, not counting unclaimed fees.
+    pub escrowed: Amount,
+}
+
+/// Basis points in one whole: a fee can't exceed the amount it's charged on.
+pub const MAX_BPS: u16 = 10_000;
+
+/// Which value movements the protocol fee is taken from.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, Enum, PartialEq, Eq)]
+pub enum FeeBasis {
+    #[default]
+    Withdrawals,
+    Deposits,
+}
+
+/// Protocol fee policy, set by an admin. No fee is charged without a treasury, which claims
+/// the fees with `ClaimFees`.
+#[derive(Clone, Debug, Default, Deserialize, InputObject, Serialize, SimpleObject)]
+#[graphql(input_name = "FeeConfigInput")]
+pub struct FeeConfig {
+    /// Fee in basis points of each charged movement, at most `MAX_BPS`.
+    pub bps: u16,
+    pub basis: FeeBasis,
+    /// Account that may claim the fees, on this chain.
+    pub treasury: Option<AccountOwner>,
+    /// Most charged on a single movement.
+    pub max_fee: Option<Amount>,
+}
+
+impl FeeConfig {
+    /// The fee due on `amount` moved on `basis`
+    pub fn fee_for(&self, basis: FeeBasis, amount: Amount) -> Amount {
+        if self.basis != basis || self.treasury.is_none() {
+            return Amount::ZERO;
+        }
+        // Split the division so `amount * bps` can't overflow
+        let (amount, bps) = (u128::from(amount), u128::from(self.bps));
+        let fee = Amount::from_attos(amount / 10_000 * bps + amount % 10_000 * bps / 10_000);
+        self.max_fee.map_or(fee, |max_fee| fee.min(max_fee))
+    }
+}
+
+/// An entry in the log of administrative changes. Only the fields relevant to `kind` are set.
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct AdminAction {
+    pub admin: AccountOwner,
+    pub kind: AdminActionKind,
+    pub timestamp: Timestamp,
+    /// The token allowed or disallowed.
+    pub token: Option<ApplicationId>,
+    /// The account granted or losing admin rights.
+    pub account: Option<AccountOwner>,
+    /// Whether the pause was switched on or off.
+    pub paused: Option<bool>,
+    /// The new fee configuration.
+    pub fee: Option<FeeConfig>,
+}
+
+impl AdminAction {
+    pub fn new(admin: AccountOwner, kind: AdminActionKind, timestamp: Timestamp) -> Self {
+        AdminAction {
+            admin,
+            kind,
+            timestamp,
+            token: None,
+            account: None,
+            paused: None,
+            fee: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Enum, Copy, PartialEq, Eq)]
+pub enum AdminActionKind {
+    AllowToken,
+    DisallowToken,
+    SetFeeConfig,
+    PauseCreation,
+    PauseWithdrawals,
+    AddAdmin,
+    RemoveAdmin,
+    TransferAdmin,
+}
+
+/// Protocol fees collected in one token, or in the native token.
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct FeeTotal {
+    pub token: Option<ApplicationId>,
+    /// Charged so far, claimed or not.
+    pub amount: Amount,
+    /// Waiting for the treasury to claim it.
+    pub unclaimed: Amount,
+}
+
+/// Totals across the streams an account sends or receives in one token, as of the query time.
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct AccountSummary {
+    pub owner: AccountOwner,
+    /// Token application the amounts are in, or `None` for the native token.
+    pub token: Option<ApplicationId>,
+    /// Sum of the rates of the account's active outgoing streams.
+    pub outflow_rate: Amount,
+    /// Sum of the rates of the account's active incoming streams.
+    pub inflow_rate: Amount,
+    /// Outflow minus inflow per second, or zero if the account is a net receiver.
+    pub net_burn_rate: Amount,
+    /// Deposited into the account's outgoing streams and not yet withdrawn by recipients.
+    pub total_locked: Amount,
+    /// Earned on the account's incoming streams and available to withdraw now.
+    pub total_withdrawable: Amount,
+    /// When the unstreamed deposits of the outgoing streams run out at the current net burn
+    /// rate, or `None` if the account is not burning funds.
+    pub funds_depleted_at: Option<Timestamp>,
+}
+
+impl AccountSummary {
+    /// The summary of an account with no streams in `token`
+    pub fn empty(owner: AccountOwner, token: Option<ApplicationId>) -> Self {
+        AccountSummary {
+            owner,
+            token,
+            outflow_rate: Amount::ZERO,
+            inflow_rate: Amount::ZERO,
+            net_burn_rate: Amount::ZERO,
+            total_locked: Amount::ZERO,
+            total_withdrawable: Amount::ZERO,
+            funds_depleted_at: None,
+        }
+    }
+}
+
+/// A merchant's standing authorization to pull a fixed amount from a subscriber every period.
+///
+/// Periods are counted from `start_time`, and each can be charged once, at any time while it
+/// is current. A period that ends without a successful charge counts as a missed payment.
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct Subscription {
+    pub id: u64,
+    pub subscriber: AccountOwner,
+    pub merchant: AccountOwner,
+    pub token: ApplicationId,
+    pub amount_per_period: Amount,
+    pub period_seconds: u64,
+    pub cap: Option<Amount>,
+    pub start_time: Timestamp,
+    /// Index of the first period that hasn't been charged or missed yet.
+    pub next_period: u64,
+    /// Start of `next_period`, when the next charge becomes due.
+    pub next_charge_at: Timestamp,
+    pub total_charged: Amount,
+    pub missed_payments: u64,
+    pub status: SubscriptionStatus,
+    pub ended_at: Option<Timestamp>,
+    pub cancelled_by: Option<AccountOwner>,
+}
+
+impl Subscription {
+    /// Index of the period that contains `time`
+    pub fn period_at(&self, time: Timestamp) -> u64 {
+        time.delta_since(self.start_time).as_micros() / self.period_micros()
+    }
+
+    /// When period `period` begins
+    pub fn period_start(&self, period: u64) -> Timestamp {
+        let offset = period.saturating_mul(self.period_micros());
+        Timestamp::from(self.start_time.micros().saturating_add(offset))
+    }
+
+    /// Whether the cap leaves room for another full payment
+    pub fn can_charge_again(&self) -> bool {
+        self.cap.is_none_or(|cap| {
+            self.total_charged
+                .try_add(self.amount_per_period)
+                .is_ok_and(|total| total <= cap)
+        })
+    }
+
+    fn period_micros(&self) -> u64 {
+        self.period_seconds.saturating_mul(1_000_000)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Enum, Copy, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    Active,
+    Cancelled,
+    /// The cap has been reached.
+    Completed,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, Enum, Copy, PartialEq, Eq)]
@@ -77,6 +668,211 @@ pub enum StreamStatus {
     Paused,
     Completed,
     Stopped,
+    /// Escrow is frozen until the arbiter rules.
+    Disputed,
+    /// The arbiter has split the escrow and the stream is over.
+    Resolved,
+    /// Reversed by the sender during the clawback window.
+    ClawedBack,
+}
+
+/// A lifecycle change recorded in the update feed.
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct StreamUpdate {
+    pub stream_id: u64,
+    pub sender: AccountOwner,
+    pub recipient: AccountOwner,
+    pub kind: StreamUpdateKind,
+    pub timestamp: Timestamp,
+}
+
+impl StreamUpdate {
+    /// Check whether the account is the sender or the recipient of the updated stream
+    pub fn involves(&self, account: &AccountOwner) -> bool {
+        self.sender == *account || self.recipient == *account
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Enum, Copy, PartialEq, Eq)]
+pub enum StreamUpdateKind {
+    Created,
+    Paused,
+    Resumed,
+    Stopped,
+    Withdrawn,
+    ToppedUp,
+    Completed,
+    RateChanged,
+    UsageReported,
+    MilestoneApproved,
+    DisputeRaised,
+    DisputeResolved,
+    ClawedBack,
+}
+
+/// An entry in a stream's audit trail.
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct StreamActivity {
+    pub kind: StreamUpdateKind,
+    /// Who performed the action, or `None` for automatic transitions like completion.
+    pub actor: Option<AccountOwner>,
+    /// Value moved, for withdrawals, top-ups, billed usage, the refund on stop or clawback
+    /// and the payouts of a dispute ruling.
+    pub amount: Option<Amount>,
+    /// Rate in effect from this point, for creation and rate changes.
+    pub rate_per_second: Option<Amount>,
+    pub timestamp: Timestamp,
+}
+
+/// Name of the event stream the stream application emits [`StreamEvent`]s on.
+pub const STREAM_EVENTS: &[u8] = b"stream_events";
+
+/// Emitted for every state transition and value movement of a stream.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum StreamEvent {
+    Created {
+        stream_id: u64,
+        sender: AccountOwner,
+        recipient: AccountOwner,
+        rate_per_second: Amount,
+        end_time: Option<Timestamp>,
+        token: Option<ApplicationId>,
+    },
+    Paused {
+        stream_id: u64,
+    },
+    Resumed {
+        stream_id: u64,
+    },
+    Stopped {
+        stream_id: u64,
+        /// Unstreamed deposit returned to the sender.
+        refunded: Amount,
+    },
+    Completed {
+        stream_id: u64,
+    },
+    Withdrawn {
+        stream_id: u64,
+        recipient: AccountOwner,
+        /// Taken from the stream, including the fee.
+        amount: Amount,
+        fee: Amount,
+    },
+    ToppedUp {
+        stream_id: u64,
+        sender: AccountOwner,
+        /// Credited to the stream, after the fee.
+        amount: Amount,
+        fee: Amount,
+    },
+    RateChanged {
+        stream_id: u64,
+        old_rate: Amount,
+        new_rate: Amount,
+    },
+    UsageReported {
+        stream_id: u64,
+        units: u64,
+        /// Billed after the per-period cap.
+        amount: Amount,
+    },
+    MilestoneApproved {
+        stream_id: u64,
+        milestone: u32,
+        name: String,
+    },
+    DisputeRaised {
+        stream_id: u64,
+        raised_by: AccountOwner,
+    },
+    DisputeResolved {
+        stream_id: u64,
+        recipient_amount: Amount,
+        sender_amount: Amount,
+    },
+    ClawedBack {
+        stream_id: u64,
+        /// Everything the recipient hadn't withdrawn.
+        refunded: Amount,
+    },
+    SubscriptionCreated {
+        subscription_id: u64,
+        subscriber: AccountOwner,
+        merchant: AccountOwner,
+        amount_per_period: Amount,
+        period_seconds: u64,
+    },
+    SubscriptionCharged {
+        subscription_id: u64,
+        period: u64,
+        amount: Amount,
+    },
+    /// Periods ended without the merchant collecting a payment.
+    SubscriptionPaymentsMissed {
+        subscription_id: u64,
+        periods: u64,
+    },
+    SubscriptionCancelled {
+        subscription_id: u64,
+        cancelled_by: AccountOwner,
+    },
+}
+
+impl StreamEvent {
+    /// The matching entry kind in the update feed, or `None` for subscription events,
+    /// which aren't about a stream
+    pub fn kind(&self) -> Option<StreamUpdateKind> {
+        match self {
+            StreamEvent::Created { .. } => Some(StreamUpdateKind::Created),
+            StreamEvent::Paused { .. } => Some(StreamUpdateKind::Paused),
+            StreamEvent::Resumed { .. } => Some(StreamUpdateKind::Resumed),
+            StreamEvent::Stopped { .. } => Some(StreamUpdateKind::Stopped),
+            StreamEvent::Completed { .. } => Some(StreamUpdateKind::Completed),
+            StreamEvent::Withdrawn { .. } => Some(StreamUpdateKind::Withdrawn),
+            StreamEvent::ToppedUp { .. } => Some(StreamUpdateKind::ToppedUp),
+            StreamEvent::RateChanged { .. } => Some(StreamUpdateKind::RateChanged),
+            StreamEvent::UsageReported { .. } => Some(StreamUpdateKind::UsageReported),
+            StreamEvent::MilestoneApproved { .. } => Some(StreamUpdateKind::MilestoneApproved),
+            StreamEvent::DisputeRaised { .. } => Some(StreamUpdateKind::DisputeRaised),
+            StreamEvent::DisputeResolved { .. } => Some(StreamUpdateKind::DisputeResolved),
+            StreamEvent::ClawedBack { .. } => Some(StreamUpdateKind::ClawedBack),
+            StreamEvent::SubscriptionCreated { .. }
+            | StreamEvent::SubscriptionCharged { .. }
+            | StreamEvent::SubscriptionPaymentsMissed { .. }
+            | StreamEvent::SubscriptionCancelled { .. } => None,
+        }
+    }
+
+    /// The value moved by this event, if any
+    pub fn amount(&self) -> Option<Amount> {
+        match self {
+            StreamEvent::Withdrawn { amount, .. }
+            | StreamEvent::ToppedUp { amount, .. }
+            | StreamEvent::UsageReported { amount, .. }
+            | StreamEvent::SubscriptionCharged { amount, .. } => Some(*amount),
+            StreamEvent::Stopped { refunded, .. } | StreamEvent::ClawedBack { refunded, .. } => {
+                Some(*refunded)
+            }
+            StreamEvent::DisputeResolved {
+                recipient_amount,
+                sender_amount,
+                ..
+            } => Some(recipient_amount.saturating_add(*sender_amount)),
+            _ => None,
+        }
+    }
+
+    /// The rate that takes effect with this event, if any
+    pub fn rate_per_second(&self) -> Option<Amount> {
+        match self {
+            StreamEvent::Created {
+                rate_per_second, ..
+            } => Some(*rate_per_second),
+            StreamEvent::RateChanged { new_rate, .. } => Some(*new_rate),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -90,5 +886,589 @@ pub enum StreamMessage {
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct StreamParameters {
+    /// Default token for new streams. It is always on the allowlist at instantiation.
     pub token_app: ApplicationId<token::TokenAbi>,
+    /// Highest protocol fee admins may set, in basis points, at most `MAX_BPS`.
+    #[serde(default)]
+    pub max_fee_bps: u16,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StreamInstantiationArgument {
+    /// Accounts with admin rights. Defaults to the account creating the application.
+    pub admins: Vec<AccountOwner>,
+    /// Token applications allowed in addition to the `token_app` parameter.
+    pub allowed_tokens: Vec<ApplicationId>,
+    pub fee: FeeConfig,
+}
+
+/// Criteria for selecting streams in paginated queries. Unset fields match everything.
+#[derive(Clone, Debug, Default, InputObject)]
+pub struct StreamFilter {
+    pub sender: Option<AccountOwner>,
+    pub recipient: Option<AccountOwner>,
+    /// Matches streams where this account is either the sender or the recipient.
+    pub counterparty: Option<AccountOwner>,
+    pub token: Option<ApplicationId>,
+    /// Matches natively funded streams if true, token-funded streams if false.
+    pub native: Option<bool>,
+    pub status: Option<Vec<StreamStatus>>,
+    pub started_after: Option<Timestamp>,
+    pub started_before: Option<Timestamp>,
+    pub min_rate: Option<Amount>,
+    pub max_rate: Option<Amount>,
+}
+
+impl StreamFilter {
+    /// Check whether a stream satisfies every criterion set on this filter
+    pub fn matches(&self, stream: &Stream) -> bool {
+        if self.sender.is_some_and(|sender| stream.sender != sender) {
+            return false;
+        }
+        if self
+            .recipient
+            .is_some_and(|recipient| stream.recipient != recipient)
+        {
+            return false;
+        }
+        if self.counterparty.is_some_and(|counterparty| {
+            stream.sender != counterparty && stream.recipient != counterparty
+        }) {
+            return false;
+        }
+        if self.token.is_some_and(|token| stream.token != Some(token)) {
+            return false;
+        }
+        if self
+            .native
+            .is_some_and(|native| stream.token.is_none() != native)
+        {
+            return false;
+        }
+        if let Some(statuses) = &self.status {
+            if !statuses.contains(&stream.status) {
+                return false;
+            }
+        }
+        if self
+            .started_after
+            .is_some_and(|time| stream.start_time < time)
+        {
+            return false;
+        }
+        if self
+            .started_before
+            .is_some_and(|time| stream.start_time > time)
+        {
+            return false;
+        }
+        if self
+            .min_rate
+            .is_some_and(|rate| stream.rate_per_second < rate)
+        {
+            return false;
+        }
+        if self
+            .max_rate
+            .is_some_and(|rate| stream.rate_per_second > rate)
+        {
+            return false;
+        }
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Enum, PartialEq, Eq)]
+pub enum StreamOrder {
+    #[default]
+    IdAsc,
+    IdDesc,
+    StartTimeAsc,
+    StartTimeDesc,
+    RateAsc,
+    RateDesc,
+}
+
+impl StreamOrder {
+    /// Compare two streams, breaking ties by stream ID so the order is total
+    pub fn compare(self, a: &Stream, b: &Stream) -> Ordering {
+        match self {
+            StreamOrder::IdAsc => a.id.cmp(&b.id),
+            StreamOrder::IdDesc => b.id.cmp(&a.id),
+            StreamOrder::StartTimeAsc => a.start_time.cmp(&b.start_time).then(a.id.cmp(&b.id)),
+            StreamOrder::StartTimeDesc => b.start_time.cmp(&a.start_time).then(b.id.cmp(&a.id)),
+            StreamOrder::RateAsc => a
+                .rate_per_second
+                .cmp(&b.rate_per_second)
+                .then(a.id.cmp(&b.id)),
+            StreamOrder::RateDesc => b
+                .rate_per_second
+                .cmp(&a.rate_per_second)
+                .then(b.id.cmp(&a.id)),
+        }
+    }
+
+    /// Whether this order follows stream IDs, which lets pages be read lazily
+    pub fn is_by_id(self) -> bool {
+        matches!(self, StreamOrder::IdAsc | StreamOrder::IdDesc)
+    }
+
+    /// The value this order sorts `stream` by, before the stream ID tie-break
+    pub fn sort_key(self, stream: &Stream) -> u128 {
+        match self {
+            StreamOrder::IdAsc | StreamOrder::IdDesc => u128::from(stream.id),
+            StreamOrder::StartTimeAsc | StreamOrder::StartTimeDesc => {
+                u128::from(stream.start_time.micros())
+            }
+            StreamOrder::RateAsc | StreamOrder::RateDesc => u128::from(stream.rate_per_second),
+        }
+    }
+
+    /// The cursor pointing at `stream` in this order
+    pub fn cursor(self, stream: &Stream) -> StreamCursor {
+        StreamCursor {
+            key: (!self.is_by_id()).then(|| self.sort_key(stream)),
+            id: stream.id,
+        }
+    }
+
+    /// Whether `stream` comes strictly after the position `(key, id)` in this order
+    pub fn is_after(self, stream: &Stream, key: u128, id: u64) -> bool {
+        let position = (self.sort_key(stream), stream.id);
+        match self {
+            StreamOrder::IdAsc | StreamOrder::StartTimeAsc | StreamOrder::RateAsc => {
+                position > (key, id)
+            }
+            StreamOrder::IdDesc | StreamOrder::StartTimeDesc | StreamOrder::RateDesc => {
+                position < (key, id)
+            }
+        }
+    }
+}
+
+/// Position in a `streams` page. ID orders use the bare stream ID; other orders also carry
+/// the sort key, encoded as `key:id`, so a page resumes from the same spot even if that
+/// stream has since changed or left the filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamCursor {
+    pub key: Option<u128>,
+    pub id: u64,
+}
+
+impl CursorType for StreamCursor {
+    type Error = String;
+
+    fn decode_cursor(s: &str) -> Result<Self, Self::Error> {
+        let invalid = || format!("Invalid stream cursor: {}", s);
+        match s.split_once(':') {
+            Some((key, id)) => Ok(StreamCursor {
+                key: Some(key.parse().map_err(|_| invalid())?),
+                id: id.parse().map_err(|_| invalid())?,
+            }),
+            None => Ok(StreamCursor {
+                key: None,
+                id: s.parse().map_err(|_| invalid())?,
+            }),
+        }
+    }
+
+    fn encode_cursor(&self) -> String {
+        match self.key {
+            Some(key) => format!("{}:{}", key, self.id),
+            None => self.id.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::connection::CursorType;
+    use linera_sdk::linera_base_types::{AccountOwner, Amount, Timestamp};
+
+    use super::{
+        FeeBasis, FeeConfig, Metering, Milestone, Stream, StreamCursor, StreamOrder, StreamStatus,
+        MAX_BPS,
+    };
+
+    fn secs(seconds: u64) -> Timestamp {
+        Timestamp::from(seconds * 1_000_000)
+    }
+
+    fn attos(attos: u128) -> Amount {
+        Amount::from_attos(attos)
+    }
+
+    /// An active stream started at zero, paying 10 attos a second out of a 1000 atto deposit
+    fn linear_stream() -> Stream {
+        Stream {
+            id: 1,
+            sender: AccountOwner::Address20([1; 20]),
+            recipient: AccountOwner::Address20([2; 20]),
+            rate_per_second: attos(10),
+            start_time: secs(0),
+            end_time: None,
+            paused_at: None,
+            total_deposited: attos(1000),
+            total_withdrawn: Amount::ZERO,
+            total_refunded: Amount::ZERO,
+            status: StreamStatus::Active,
+            checkpoint_time: secs(0),
+            streamed_at_checkpoint: Amount::ZERO,
+            token: None,
+            metering: None,
+            milestones: Vec::new(),
+            arbiter: None,
+            dispute: None,
+            irrevocable: false,
+            clawback_until: None,
+        }
+    }
+
+    #[test]
+    fn streams_whole_seconds_at_the_rate() {
+        let stream = linear_stream();
+        assert_eq!(stream.streamed_at(secs(0)), Amount::ZERO);
+        assert_eq!(stream.streamed_at(secs(30)), attos(300));
+        assert_eq!(stream.streamed_at(Timestamp::from(30_999_999)), attos(300));
+    }
+
+    #[test]
+    fn never_streams_more_than_the_deposit() {
+        let stream = linear_stream();
+        assert_eq!(stream.streamed_at(secs(100)), attos(1000));
+        assert_eq!(stream.streamed_at(secs(10_000)), attos(1000));
+        assert_eq!(stream.accrued_at(secs(10_000)), attos(100_000));
+    }
+
+    #[test]
+    fn accrues_from_the_checkpoint() {
+        let stream = Stream {
+            rate_per_second: attos(2),
+            checkpoint_time: secs(10),
+            streamed_at_checkpoint: attos(50),
+            ..linear_stream()
+        };
+        assert_eq!(stream.streamed_at(secs(5)), attos(50));
+        assert_eq!(stream.streamed_at(secs(10)), attos(50));
+        assert_eq!(stream.streamed_at(secs(20)), attos(70));
+    }
+
+    #[test]
+    fn a_checkpoint_past_the_deposit_is_paid_after_a_top_up() {
+        // Ran dry at 100 seconds, then the rate changed at 150 seconds
+        let stream = Stream {
+            rate_per_second: attos(1),
+            checkpoint_time: secs(150),
+            streamed_at_checkpoint: linear_stream().accrued_at(secs(150)),
+            ..linear_stream()
+        };
+        assert_eq!(stream.streamed_at(secs(160)), attos(1000));
+
+        let topped_up = Stream {
+            total_deposited: attos(2000),
+            ..stream
+        };
+        assert_eq!(topped_up.streamed_at(secs(160)), attos(1510));
+    }
+
+    #[test]
+    fn stops_accruing_at_the_end_time_and_while_paused() {
+        let ended = Stream {
+            end_time: Some(secs(20)),
+            ..linear_stream()
+        };
+        assert_eq!(ended.streamed_at(secs(50)), attos(200));
+
+        let completed = Stream {
+            status: StreamStatus::Completed,
+            ..ended
+        };
+        assert_eq!(completed.effective_time(secs(50)), secs(20));
+        assert_eq!(completed.streamed_at(secs(50)), attos(200));
+
+        let paused = Stream {
+            status: StreamStatus::Paused,
+            paused_at: Some(secs(10)),
+            ..linear_stream()
+        };
+        assert_eq!(paused.streamed_at(secs(50)), attos(100));
+    }
+
+    #[test]
+    fn settled_streams_report_what_was_paid() {
+        for status in [StreamStatus::Resolved, StreamStatus::ClawedBack] {
+            let stream = Stream {
+                status,
+                total_withdrawn: attos(120),
+                ..linear_stream()
+            };
+            assert_eq!(stream.streamed_at(secs(50)), attos(120));
+        }
+    }
+
+    /// Metering at 3 attos a unit, capped at 100 attos per 60 second window
+    fn metering() -> Metering {
+        Metering {
+            meter: AccountOwner::Address20([3; 20]),
+            price_per_unit: attos(3),
+            period_seconds: 60,
+            cap_per_period: Some(attos(100)),
+            total_units: 0,
+            period_start: secs(0),
+            billed_in_period: Amount::ZERO,
+        }
+    }
+
+    #[test]
+    fn bills_units_at_the_price() {
+        let mut metering = Metering {
+            cap_per_period: None,
+            ..metering()
+        };
+        assert_eq!(metering.bill(10, secs(1)), attos(30));
+        assert_eq!(metering.bill(50, secs(2)), attos(150));
+        assert_eq!(metering.total_units, 60);
+    }
+
+    #[test]
+    fn clips_billing_to_the_cap_until_the_next_window() {
+        let mut metering = metering();
+        assert_eq!(metering.bill(30, secs(1)), attos(90));
+        assert_eq!(metering.bill(30, secs(2)), attos(10));
+        assert_eq!(metering.bill(30, secs(59)), Amount::ZERO);
+        assert_eq!(metering.total_units, 90);
+
+        // Two windows later the cap is fresh and the window is aligned to the period
+        assert_eq!(metering.bill(10, secs(130)), attos(30));
+        assert_eq!(metering.period_start, secs(120));
+        assert_eq!(metering.billed_in_period, attos(30));
+    }
+
+    /// A 1000 atto milestone released over 100 seconds once unlocked
+    fn milestone() -> Milestone {
+        Milestone {
+            name: "Design".to_string(),
+            amount: attos(1000),
+            deadline: None,
+            release_seconds: 100,
+            approved_at: None,
+        }
+    }
+
+    #[test]
+    fn milestones_unlock_on_approval_or_deadline() {
+        let pending = milestone();
+        assert_eq!(pending.unlocked_at(secs(1000)), None);
+
+        let approved = Milestone {
+            approved_at: Some(secs(10)),
+            deadline: Some(secs(30)),
+            ..milestone()
+        };
+        assert_eq!(approved.unlocked_at(secs(5)), None);
+        assert_eq!(approved.unlocked_at(secs(20)), Some(secs(10)));
+
+        let lapsed = Milestone {
+            approved_at: Some(secs(40)),
+            deadline: Some(secs(30)),
+            ..milestone()
+        };
+        assert_eq!(lapsed.unlocked_at(secs(35)), Some(secs(30)));
+        assert_eq!(lapsed.unlocked_at(secs(50)), Some(secs(30)));
+    }
+
+    #[test]
+    fn milestones_release_over_their_release_time() {
+        let approved = Milestone {
+            approved_at: Some(secs(10)),
+            ..milestone()
+        };
+        assert_eq!(approved.released_at(secs(5)), Amount::ZERO);
+        assert_eq!(approved.released_at(secs(35)), attos(250));
+        assert_eq!(approved.released_at(secs(110)), attos(1000));
+
+        let instant = Milestone {
+            release_seconds: 0,
+            ..approved
+        };
+        assert_eq!(instant.released_at(secs(10)), attos(1000));
+    }
+
+    #[test]
+    fn resuming_moves_milestones_past_the_pause() {
+        let mut before = Milestone {
+            approved_at: Some(secs(10)),
+            deadline: Some(secs(25)),
+            ..milestone()
+        };
+        before.resume(secs(20), secs(50));
+        assert_eq!(before.approved_at, Some(secs(40)));
+        assert_eq!(before.deadline, Some(secs(55)));
+
+        let mut during = Milestone {
+            approved_at: Some(secs(30)),
+            ..milestone()
+        };
+        during.resume(secs(20), secs(50));
+        assert_eq!(during.approved_at, Some(secs(50)));
+    }
+
+    #[test]
+    fn milestone_streams_pay_released_milestones_up_to_the_deposit() {
+        let stream = Stream {
+            rate_per_second: Amount::ZERO,
+            total_deposited: attos(1500),
+            milestones: vec![
+                Milestone {
+                    approved_at: Some(secs(0)),
+                    release_seconds: 0,
+                    ..milestone()
+                },
+                Milestone {
+                    approved_at: Some(secs(10)),
+                    ..milestone()
+                },
+            ],
+            ..linear_stream()
+        };
+        assert_eq!(stream.streamed_at(secs(5)), attos(1000));
+        assert_eq!(stream.streamed_at(secs(60)), attos(1500));
+
+        let paused = Stream {
+            status: StreamStatus::Paused,
+            paused_at: Some(secs(30)),
+            ..stream
+        };
+        assert_eq!(paused.streamed_at(secs(60)), attos(1200));
+    }
+
+    /// A 2.5% fee on withdrawals, paid to a treasury
+    fn fee_config() -> FeeConfig {
+        FeeConfig {
+            bps: 250,
+            basis: FeeBasis::Withdrawals,
+            treasury: Some(AccountOwner::Address20([4; 20])),
+            max_fee: None,
+        }
+    }
+
+    #[test]
+    fn fees_are_charged_in_basis_points_on_their_basis() {
+        let config = fee_config();
+        assert_eq!(
+            config.fee_for(FeeBasis::Withdrawals, attos(10_000)),
+            attos(250)
+        );
+        assert_eq!(config.fee_for(FeeBasis::Withdrawals, attos(399)), attos(9));
+        assert_eq!(
+            config.fee_for(FeeBasis::Deposits, attos(10_000)),
+            Amount::ZERO
+        );
+    }
+
+    #[test]
+    fn fees_need_a_treasury_and_respect_the_maximum() {
+        let untreasured = FeeConfig {
+            treasury: None,
+            ..fee_config()
+        };
+        assert_eq!(
+            untreasured.fee_for(FeeBasis::Withdrawals, attos(10_000)),
+            Amount::ZERO
+        );
+
+        let capped = FeeConfig {
+            max_fee: Some(attos(100)),
+            ..fee_config()
+        };
+        assert_eq!(
+            capped.fee_for(FeeBasis::Withdrawals, attos(10_000)),
+            attos(100)
+        );
+        assert_eq!(
+            capped.fee_for(FeeBasis::Withdrawals, attos(2_000)),
+            attos(50)
+        );
+    }
+
+    #[test]
+    fn fees_do_not_overflow_on_large_amounts() {
+        let config = FeeConfig {
+            bps: MAX_BPS,
+            ..fee_config()
+        };
+        assert_eq!(
+            config.fee_for(FeeBasis::Withdrawals, Amount::MAX),
+            Amount::MAX
+        );
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        let by_id = StreamCursor { key: None, id: 42 };
+        assert_eq!(by_id.encode_cursor(), "42");
+        assert_eq!(StreamCursor::decode_cursor("42"), Ok(by_id));
+
+        let by_key = StreamCursor {
+            key: Some(7_000_000),
+            id: 42,
+        };
+        assert_eq!(by_key.encode_cursor(), "7000000:42");
+        assert_eq!(StreamCursor::decode_cursor("7000000:42"), Ok(by_key));
+    }
+
+    #[test]
+    fn malformed_cursors_are_rejected() {
+        for cursor in ["", "x", "1:", ":1", "1:x", "1:2:3", "-1"] {
+            assert!(StreamCursor::decode_cursor(cursor).is_err(), "{cursor}");
+        }
+    }
+
+    #[test]
+    fn only_non_id_orders_put_the_sort_key_in_the_cursor() {
+        let stream = Stream {
+            id: 3,
+            start_time: secs(5),
+            ..linear_stream()
+        };
+        assert_eq!(
+            StreamOrder::IdDesc.cursor(&stream),
+            StreamCursor { key: None, id: 3 }
+        );
+        assert_eq!(
+            StreamOrder::StartTimeAsc.cursor(&stream),
+            StreamCursor {
+                key: Some(5_000_000),
+                id: 3
+            }
+        );
+        assert_eq!(
+            StreamOrder::RateDesc.cursor(&stream),
+            StreamCursor {
+                key: Some(10),
+                id: 3
+            }
+        );
+    }
+
+    #[test]
+    fn seeks_strictly_past_the_cursor_position() {
+        let stream = Stream {
+            id: 3,
+            ..linear_stream()
+        };
+
+        // Ascending: a larger key, or the same key with a larger ID
+        assert!(StreamOrder::RateAsc.is_after(&stream, 9, 10));
+        assert!(StreamOrder::RateAsc.is_after(&stream, 10, 2));
+        assert!(!StreamOrder::RateAsc.is_after(&stream, 10, 3));
+        assert!(!StreamOrder::RateAsc.is_after(&stream, 11, 0));
+
+        // Descending: a smaller key, or the same key with a smaller ID
+        assert!(StreamOrder::RateDesc.is_after(&stream, 11, 0));
+        assert!(StreamOrder::RateDesc.is_after(&stream, 10, 4));
+        assert!(!StreamOrder::RateDesc.is_after(&stream, 10, 3));
+        assert!(!StreamOrder::RateDesc.is_after(&stream, 9, 10));
+    }
 }
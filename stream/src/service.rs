@@ -2,18 +2,30 @@
 
 mod state;
 
-use async_graphql::{EmptySubscription, Object, Request, Response, Schema};
+use async_graphql::{
+    connection::{query, Connection, Edge},
+    EmptySubscription, Object, Request, Response, Schema,
+};
 use linera_sdk::{
     graphql::GraphQLMutationRoot,
-    linera_base_types::{AccountOwner, WithServiceAbi},
-    views::View,
+    linera_base_types::{AccountOwner, Amount, ApplicationId, Timestamp, WithServiceAbi},
+    views::{LogView, MapView, View, ViewError},
     Service, ServiceRuntime,
 };
-use std::sync::Arc;
-use stream::{Stream, StreamAbi, StreamOperation, StreamParameters};
+use std::{collections::BTreeMap, sync::Arc};
+use stream::{
+    AccountSummary, AdminAction, ClawbackWindow, FeeConfig, FeeTotal, MilestoneProgress, Stream,
+    StreamAbi, StreamActivity, StreamCursor, StreamFilter, StreamOperation, StreamOrder,
+    StreamParameters, StreamStatus, StreamUpdate, Subscription, TokenTotals,
+};
 
 use self::state::StreamState;
 
+/// Page size used when a connection query doesn't specify `first`.
+const DEFAULT_PAGE_SIZE: usize = 20;
+/// Upper bound on `first` so a single query can't load the whole chain.
+const MAX_PAGE_SIZE: usize = 100;
+
 #[derive(Clone)]
 pub struct StreamService {
     state: Arc<StreamState>,
@@ -56,6 +68,96 @@ impl StreamService {
         self.state.streams.get(&id).await.ok().flatten()
     }
 
+    /// Streams matching `filter`, sorted by `order`, as a cursor-paginated connection.
+    /// Cursors are stream IDs for ID orders and `key:id` pairs for the others.
+    async fn streams(
+        &self,
+        filter: Option<StreamFilter>,
+        order: Option<StreamOrder>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> async_graphql::Result<Connection<StreamCursor, Stream>> {
+        let filter = filter.unwrap_or_default();
+        let order = order.unwrap_or_default();
+        query(
+            after,
+            None,
+            first,
+            None,
+            |after, _before, first, _last| async move {
+                let limit = first.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+                self.page(&filter, order, after, limit).await
+            },
+        )
+        .await
+    }
+
+    async fn subscription(&self, id: u64) -> Option<Subscription> {
+        self.state.subscriptions.get(&id).await.ok().flatten()
+    }
+
+    /// Subscriptions paid by `subscriber` and/or collected by `merchant`, in ID order, as a
+    /// cursor-paginated connection. Cursors are subscription IDs.
+    ///
+    /// `missedPayments` only counts periods up to the merchant's last charge attempt.
+    async fn subscriptions(
+        &self,
+        subscriber: Option<AccountOwner>,
+        merchant: Option<AccountOwner>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> async_graphql::Result<Connection<u64, Subscription>> {
+        query(
+            after,
+            None,
+            first,
+            None,
+            |after: Option<u64>, _before, first, _last| async move {
+                let limit = first.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+                let ids = match (&subscriber, &merchant) {
+                    (Some(subscriber), _) => {
+                        self.indexed_ids(&self.state.subscriptions_by_subscriber, subscriber)
+                            .await
+                    }
+                    (None, Some(merchant)) => {
+                        self.indexed_ids(&self.state.subscriptions_by_merchant, merchant)
+                            .await
+                    }
+                    (None, None) => (1..*self.state.next_subscription_id.get()).collect(),
+                };
+
+                let mut subscriptions = Vec::new();
+                for id in ids
+                    .into_iter()
+                    .filter(|id| after.is_none_or(|cursor| *id > cursor))
+                {
+                    let Some(subscription) = self.state.subscriptions.get(&id).await? else {
+                        continue;
+                    };
+                    if merchant.is_some_and(|merchant| subscription.merchant != merchant) {
+                        continue;
+                    }
+                    subscriptions.push(subscription);
+                    if subscriptions.len() > limit {
+                        break;
+                    }
+                }
+
+                let has_next_page = subscriptions.len() > limit;
+                subscriptions.truncate(limit);
+                let mut connection = Connection::new(after.is_some(), has_next_page);
+                connection.edges.extend(
+                    subscriptions
+                        .into_iter()
+                        .map(|subscription| Edge::new(subscription.id, subscription)),
+                );
+                Ok::<_, async_graphql::Error>(connection)
+            },
+        )
+        .await
+    }
+
+    #[graphql(deprecation = "Use `streams` with a `sender` filter")]
     async fn streams_by_sender(&self, sender: String) -> Vec<Stream> {
         let owner: AccountOwner = match sender.parse() {
             Ok(o) => o,
@@ -80,6 +182,7 @@ impl StreamService {
         streams
     }
 
+    #[graphql(deprecation = "Use `streams` with a `recipient` filter")]
     async fn streams_by_recipient(&self, recipient: String) -> Vec<Stream> {
         let owner: AccountOwner = match recipient.parse() {
             Ok(o) => o,
@@ -104,17 +207,282 @@ impl StreamService {
         streams
     }
 
-    async fn earned_amount(&self, stream_id: u64) -> String {
-        // Note: Earned amount calculation requires current time from the blockchain
-        // For accurate calculation, use the contract's withdraw operation
-        // This query returns 0 as a placeholder - frontend should calculate based on stream data
-        if let Some(_stream) = self.state.streams.get(&stream_id).await.ok().flatten() {
-            "0".to_string()
-        } else {
-            "0".to_string()
+    /// Lifecycle changes after the `after` cursor, oldest first, optionally narrowed to one
+    /// stream and/or one account. Clients subscribe to the node service's `notifications`
+    /// for the chain and call this with their last cursor whenever a new block arrives.
+    ///
+    /// Filtered feeds read the per-stream or per-account index, so a page only loads the
+    /// updates it returns. Cursors are positions in the unfiltered feed either way.
+    async fn stream_updates(
+        &self,
+        stream_id: Option<u64>,
+        account: Option<AccountOwner>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> async_graphql::Result<Connection<u64, StreamUpdate>> {
+        query(
+            after,
+            None,
+            first,
+            None,
+            |after: Option<u64>, _before, first, _last| async move {
+                let limit = first.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+                let mut updates = Vec::new();
+                let positions = match (stream_id, account) {
+                    (Some(stream_id), _) => {
+                        self.state
+                            .updates_by_stream
+                            .try_load_entry(&stream_id)
+                            .await?
+                    }
+                    (None, Some(account)) => {
+                        self.state
+                            .updates_by_account
+                            .try_load_entry(&account)
+                            .await?
+                    }
+                    (None, None) => {
+                        let count = self.state.updates.count();
+                        let start = after.map_or(0, |cursor| cursor as usize + 1).min(count);
+                        let end = count.min(start + limit + 1);
+                        let page = self.state.updates.read(start..end).await?;
+                        updates.extend(
+                            page.into_iter()
+                                .enumerate()
+                                .map(|(offset, update)| ((start + offset) as u64, update)),
+                        );
+                        None
+                    }
+                };
+
+                if let Some(positions) = positions {
+                    let count = positions.count();
+                    let start = Self::seek(&positions, after).await?;
+                    let end = count.min(start + limit + 1);
+                    for position in positions.read(start..end).await? {
+                        let Some(update) = self.state.updates.get(position as usize).await? else {
+                            continue;
+                        };
+                        // A stream's updates all share its sender and recipient
+                        if account.is_some_and(|account| !update.involves(&account)) {
+                            break;
+                        }
+                        updates.push((position, update));
+                    }
+                }
+
+                let has_next_page = updates.len() > limit;
+                updates.truncate(limit);
+                let mut connection = Connection::new(after.is_some(), has_next_page);
+                connection.edges.extend(
+                    updates
+                        .into_iter()
+                        .map(|(cursor, update)| Edge::new(cursor, update)),
+                );
+                Ok::<_, async_graphql::Error>(connection)
+            },
+        )
+        .await
+    }
+
+    /// The stream's clawback window, if it was created with one.
+    async fn clawback_window(&self, stream_id: u64) -> Option<ClawbackWindow> {
+        let stream = self.state.streams.get(&stream_id).await.ok().flatten()?;
+        let until = stream.clawback_until?;
+        let current_time = self.runtime.system_time();
+        Some(ClawbackWindow {
+            until,
+            open: stream.in_clawback_window(current_time)
+                && matches!(stream.status, StreamStatus::Active | StreamStatus::Paused),
+            seconds_remaining: until.delta_since(current_time).as_micros() / 1_000_000,
+        })
+    }
+
+    /// Unlock and release status of each milestone of a milestone stream.
+    async fn milestone_progress(&self, stream_id: u64) -> Vec<MilestoneProgress> {
+        let Some(stream) = self.state.streams.get(&stream_id).await.ok().flatten() else {
+            return vec![];
+        };
+        let time = self
+            .state
+            .effective_time(&stream, self.runtime.system_time());
+        stream
+            .milestones
+            .into_iter()
+            .enumerate()
+            .map(|(index, milestone)| MilestoneProgress {
+                index: index as u32,
+                unlocked_at: milestone.unlocked_at(time),
+                released: milestone.released_at(time),
+                name: milestone.name,
+                amount: milestone.amount,
+            })
+            .collect()
+    }
+
+    /// Audit trail of a stream, oldest first, as a cursor-paginated connection.
+    async fn stream_history(
+        &self,
+        id: u64,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> async_graphql::Result<Connection<u64, StreamActivity>> {
+        query(
+            after,
+            None,
+            first,
+            None,
+            |after: Option<u64>, _before, first, _last| async move {
+                let limit = first.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+                let Some(history) = self.state.history.try_load_entry(&id).await? else {
+                    return Ok(Connection::new(false, false));
+                };
+                let count = history.count();
+                let start = after.map_or(0, |cursor| cursor as usize + 1).min(count);
+                let end = count.min(start + limit);
+                let entries = history.read(start..end).await?;
+
+                let mut connection = Connection::new(start > 0, end < count);
+                connection.edges.extend(
+                    entries
+                        .into_iter()
+                        .enumerate()
+                        .map(|(offset, entry)| Edge::new((start + offset) as u64, entry)),
+                );
+                Ok::<_, async_graphql::Error>(connection)
+            },
+        )
+        .await
+    }
+
+    /// Cursor of the most recent update, for clients that only want changes from now on.
+    async fn latest_update_cursor(&self) -> Option<String> {
+        self.state
+            .updates
+            .count()
+            .checked_sub(1)
+            .map(|index| index.to_string())
+    }
+
+    /// Aggregate outflow, inflow and escrow totals for an account in one token: `token`, the
+    /// native token with `native: true`, or else the `token_app` parameter. Amounts in
+    /// different tokens are never added together; `accountSummaries` lists every token.
+    async fn account_summary(
+        &self,
+        owner: AccountOwner,
+        token: Option<ApplicationId>,
+        native: Option<bool>,
+    ) -> AccountSummary {
+        let token = match (token, native) {
+            (Some(token), _) => Some(token),
+            (None, Some(true)) => None,
+            (None, _) => Some(self.runtime.application_parameters().token_app.forget_abi()),
+        };
+        self.summaries(owner)
+            .await
+            .remove(&token)
+            .unwrap_or_else(|| AccountSummary::empty(owner, token))
+    }
+
+    /// One `accountSummary` per token the account has streams in.
+    async fn account_summaries(&self, owner: AccountOwner) -> Vec<AccountSummary> {
+        self.summaries(owner).await.into_values().collect()
+    }
+
+    /// Token applications new streams may be funded with.
+    async fn allowed_tokens(&self) -> Vec<ApplicationId> {
+        self.state
+            .allowed_tokens
+            .indices()
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Escrow totals for every token that has funded a stream, starting with the native
+    /// token.
+    async fn token_totals(&self) -> Vec<TokenTotals> {
+        let mut totals = vec![self.state.native_totals.get().clone()];
+        for token in self.state.token_totals.indices().await.unwrap_or_default() {
+            if let Some(entry) = self.state.token_totals.get(&token).await.ok().flatten() {
+                totals.push(entry);
+            }
         }
+        totals
+    }
+
+    async fn fee_config(&self) -> FeeConfig {
+        self.state.fee_config.get().clone()
     }
 
+    /// Highest fee admins may set, in basis points, fixed by the application parameters.
+    async fn max_fee_bps(&self) -> u16 {
+        self.runtime.application_parameters().max_fee_bps
+    }
+
+    /// Protocol fees charged so far, per token, and what the treasury has yet to claim.
+    async fn accumulated_fees(&self) -> Vec<FeeTotal> {
+        self.token_totals()
+            .await
+            .into_iter()
+            .map(|totals| FeeTotal {
+                token: totals.token,
+                amount: totals.total_fees,
+                unclaimed: totals.total_fees.saturating_sub(totals.fees_claimed),
+            })
+            .collect()
+    }
+
+    async fn admins(&self) -> Vec<AccountOwner> {
+        self.state.admins.indices().await.unwrap_or_default()
+    }
+
+    async fn creation_paused(&self) -> bool {
+        *self.state.creation_paused.get()
+    }
+
+    async fn withdrawals_paused(&self) -> bool {
+        *self.state.withdrawals_paused.get()
+    }
+
+    /// Administrative changes, oldest first, as a cursor-paginated connection.
+    async fn admin_log(
+        &self,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> async_graphql::Result<Connection<u64, AdminAction>> {
+        query(
+            after,
+            None,
+            first,
+            None,
+            |after: Option<u64>, _before, first, _last| async move {
+                let limit = first.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+                let count = self.state.admin_log.count();
+                let start = after.map_or(0, |cursor| cursor as usize + 1).min(count);
+                let end = count.min(start + limit);
+                let entries = self.state.admin_log.read(start..end).await?;
+
+                let mut connection = Connection::new(start > 0, end < count);
+                connection.edges.extend(
+                    entries
+                        .into_iter()
+                        .enumerate()
+                        .map(|(offset, entry)| Edge::new((start + offset) as u64, entry)),
+                );
+                Ok::<_, async_graphql::Error>(connection)
+            },
+        )
+        .await
+    }
+
+    /// Earned on the stream and not yet withdrawn, as of the query time.
+    async fn earned_amount(&self, stream_id: u64) -> Option<Amount> {
+        let stream = self.state.streams.get(&stream_id).await.ok().flatten()?;
+        let current_time = self.runtime.system_time();
+        Some(self.state.calculate_earned_amount(&stream, current_time))
+    }
+
+    #[graphql(deprecation = "Use `streams`, which is paginated")]
     async fn all_streams(&self) -> Vec<Stream> {
         let mut streams = Vec::new();
         let stream_count = *self.state.next_stream_id.get();
@@ -128,3 +496,191 @@ impl StreamService {
         streams
     }
 }
+
+impl StreamService {
+    /// Summaries of the streams `owner` sends or receives, one per funding token
+    async fn summaries(
+        &self,
+        owner: AccountOwner,
+    ) -> BTreeMap<Option<ApplicationId>, AccountSummary> {
+        let current_time = self.runtime.system_time();
+        let mut summaries = BTreeMap::new();
+        let mut unstreamed = BTreeMap::new();
+
+        for id in self
+            .indexed_ids(&self.state.streams_by_sender, &owner)
+            .await
+        {
+            let Some(stream) = self.state.streams.get(&id).await.ok().flatten() else {
+                continue;
+            };
+            let summary = summaries
+                .entry(stream.token)
+                .or_insert_with(|| AccountSummary::empty(owner, stream.token));
+            summary.total_locked = summary.total_locked.saturating_add(stream.escrowed());
+            if self.state.is_streaming(&stream, current_time) {
+                summary.outflow_rate = summary.outflow_rate.saturating_add(stream.rate_per_second);
+                let streamed = self.state.calculate_streamed_amount(&stream, current_time);
+                let remaining = unstreamed.entry(stream.token).or_insert(Amount::ZERO);
+                *remaining =
+                    remaining.saturating_add(stream.total_deposited.saturating_sub(streamed));
+            }
+        }
+
+        for id in self
+            .indexed_ids(&self.state.streams_by_recipient, &owner)
+            .await
+        {
+            let Some(stream) = self.state.streams.get(&id).await.ok().flatten() else {
+                continue;
+            };
+            let summary = summaries
+                .entry(stream.token)
+                .or_insert_with(|| AccountSummary::empty(owner, stream.token));
+            if self.state.is_streaming(&stream, current_time) {
+                summary.inflow_rate = summary.inflow_rate.saturating_add(stream.rate_per_second);
+            }
+            summary.total_withdrawable = summary
+                .total_withdrawable
+                .saturating_add(self.state.calculate_earned_amount(&stream, current_time));
+        }
+
+        for (token, summary) in &mut summaries {
+            summary.net_burn_rate = summary.outflow_rate.saturating_sub(summary.inflow_rate);
+            let unstreamed = unstreamed.get(token).copied().unwrap_or(Amount::ZERO);
+            summary.funds_depleted_at = (summary.net_burn_rate > Amount::ZERO).then(|| {
+                let seconds = u128::from(unstreamed) / u128::from(summary.net_burn_rate);
+                let micros = u64::try_from(seconds.saturating_mul(1_000_000)).unwrap_or(u64::MAX);
+                Timestamp::from(current_time.micros().saturating_add(micros))
+            });
+        }
+        summaries
+    }
+
+    /// IDs of the streams that can match `filter`, in ascending order.
+    /// Uses the per-account indexes when the filter names an account.
+    async fn candidate_ids(&self, filter: &StreamFilter) -> Vec<u64> {
+        if let Some(sender) = &filter.sender {
+            return self
+                .indexed_ids(&self.state.streams_by_sender, sender)
+                .await;
+        }
+        if let Some(recipient) = &filter.recipient {
+            return self
+                .indexed_ids(&self.state.streams_by_recipient, recipient)
+                .await;
+        }
+        if let Some(counterparty) = &filter.counterparty {
+            let mut ids = self
+                .indexed_ids(&self.state.streams_by_sender, counterparty)
+                .await;
+            ids.extend(
+                self.indexed_ids(&self.state.streams_by_recipient, counterparty)
+                    .await,
+            );
+            ids.sort_unstable();
+            ids.dedup();
+            return ids;
+        }
+        (1..*self.state.next_stream_id.get()).collect()
+    }
+
+    /// Index of the first entry of `positions` past the `after` cursor. Positions are
+    /// appended in increasing order, so this is a binary search.
+    async fn seek(positions: &LogView<u64>, after: Option<u64>) -> Result<usize, ViewError> {
+        let Some(after) = after else {
+            return Ok(0);
+        };
+        let (mut low, mut high) = (0, positions.count());
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if positions
+                .get(middle)
+                .await?
+                .is_some_and(|position| position <= after)
+            {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        Ok(low)
+    }
+
+    async fn indexed_ids(
+        &self,
+        index: &MapView<AccountOwner, Vec<u64>>,
+        owner: &AccountOwner,
+    ) -> Vec<u64> {
+        index.get(owner).await.ok().flatten().unwrap_or_default()
+    }
+
+    /// Load up to `limit` streams following the `after` cursor.
+    ///
+    /// ID orders walk the candidate IDs and stop as soon as the page is full. Other orders
+    /// have to load every candidate, but only sort the ones that make it onto the page.
+    async fn page(
+        &self,
+        filter: &StreamFilter,
+        order: StreamOrder,
+        after: Option<StreamCursor>,
+        limit: usize,
+    ) -> async_graphql::Result<Connection<StreamCursor, Stream>> {
+        let mut ids = self.candidate_ids(filter).await;
+        let mut streams = Vec::new();
+
+        if order.is_by_id() {
+            if order == StreamOrder::IdDesc {
+                ids.reverse();
+            }
+            let ids = ids.into_iter().filter(|id| match after {
+                None => true,
+                Some(cursor) if order == StreamOrder::IdDesc => *id < cursor.id,
+                Some(cursor) => *id > cursor.id,
+            });
+            for id in ids {
+                if let Some(stream) = self.state.streams.get(&id).await.ok().flatten() {
+                    if filter.matches(&stream) {
+                        streams.push(stream);
+                        if streams.len() > limit {
+                            break;
+                        }
+                    }
+                }
+            }
+        } else {
+            // Seek past the cursor's own position rather than looking the stream up again,
+            // since it may have left the filter or changed its sort key since
+            let after = match after {
+                None => None,
+                Some(StreamCursor { key: Some(key), id }) => Some((key, id)),
+                Some(StreamCursor { key: None, .. }) => {
+                    return Err("Cursor doesn't belong to this order".into())
+                }
+            };
+            for id in ids {
+                if let Some(stream) = self.state.streams.get(&id).await.ok().flatten() {
+                    let is_after = after.is_none_or(|(key, id)| order.is_after(&stream, key, id));
+                    if is_after && filter.matches(&stream) {
+                        streams.push(stream);
+                    }
+                }
+            }
+            if streams.len() > limit + 1 {
+                streams.select_nth_unstable_by(limit, |a, b| order.compare(a, b));
+                streams.truncate(limit + 1);
+            }
+            streams.sort_by(|a, b| order.compare(a, b));
+        }
+
+        let has_next_page = streams.len() > limit;
+        streams.truncate(limit);
+        let mut connection = Connection::new(after.is_some(), has_next_page);
+        connection.edges.extend(
+            streams
+                .into_iter()
+                .map(|stream| Edge::new(order.cursor(&stream), stream)),
+        );
+        Ok(connection)
+    }
+}
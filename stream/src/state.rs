@@ -1,9 +1,15 @@
 use async_graphql::SimpleObject;
 use linera_sdk::{
-    linera_base_types::{AccountOwner, Amount, Timestamp},
-    views::{linera_views, MapView, RegisterView, RootView, ViewStorageContext},
+    linera_base_types::{AccountOwner, Amount, ApplicationId, Timestamp},
+    views::{
+        linera_views, CollectionView, LogView, MapView, RegisterView, RootView, SetView,
+        ViewStorageContext,
+    },
+};
+use stream::{
+    AdminAction, FeeConfig, Stream, StreamActivity, StreamStatus, StreamUpdate, Subscription,
+    TokenTotals,
 };
-use stream::{Stream, StreamStatus};
 
 #[derive(RootView, SimpleObject)]
 #[view(context = ViewStorageContext)]
@@ -12,35 +18,58 @@ pub struct StreamState {
     pub next_stream_id: RegisterView<u64>,
     pub streams_by_sender: MapView<AccountOwner, Vec<u64>>,
     pub streams_by_recipient: MapView<AccountOwner, Vec<u64>>,
+    /// Feed of lifecycle changes, in the order they happened. Log indexes serve as cursors.
+    pub updates: LogView<StreamUpdate>,
+    /// Indexes in `updates` of each stream's changes, so filtered feeds skip the rest.
+    pub updates_by_stream: CollectionView<u64, LogView<u64>>,
+    /// Indexes in `updates` of changes to the streams an account sends or receives.
+    pub updates_by_account: CollectionView<AccountOwner, LogView<u64>>,
+    /// Append-only audit trail of each stream, keyed by stream ID.
+    pub history: CollectionView<u64, LogView<StreamActivity>>,
+    /// Accounts allowed to change the configuration and pause the application.
+    pub admins: SetView<AccountOwner>,
+    /// Token applications new streams may be funded with.
+    pub allowed_tokens: SetView<ApplicationId>,
+    /// Escrow totals per token application, including tokens no longer allowed.
+    pub token_totals: MapView<ApplicationId, TokenTotals>,
+    /// Escrow totals of natively funded streams.
+    pub native_totals: RegisterView<TokenTotals>,
+    pub subscriptions: MapView<u64, Subscription>,
+    pub next_subscription_id: RegisterView<u64>,
+    pub subscriptions_by_subscriber: MapView<AccountOwner, Vec<u64>>,
+    pub subscriptions_by_merchant: MapView<AccountOwner, Vec<u64>>,
+    pub fee_config: RegisterView<FeeConfig>,
+    /// Emergency switch rejecting new streams and subscriptions.
+    pub creation_paused: RegisterView<bool>,
+    /// Emergency switch rejecting every payment out of escrow, and subscription charges.
+    pub withdrawals_paused: RegisterView<bool>,
+    /// Every administrative change, oldest first.
+    pub admin_log: LogView<AdminAction>,
 }
 
 impl StreamState {
     /// Calculate how much has been earned but not withdrawn
     pub fn calculate_earned_amount(&self, stream: &Stream, current_time: Timestamp) -> Amount {
-        let effective_time = match stream.status {
-            StreamStatus::Active => current_time,
-            StreamStatus::Paused => stream.paused_at.unwrap_or(current_time),
-            StreamStatus::Completed | StreamStatus::Stopped => {
-                stream.end_time.unwrap_or(current_time)
-            }
-        };
-
-        // Handle case where effective_time is before start_time
-        if effective_time <= stream.start_time {
-            return Amount::ZERO;
-        }
-
-        let elapsed = effective_time
-            .delta_since(stream.start_time)
-            .as_micros();
+        // Available = earned - withdrawn
+        self.calculate_streamed_amount(stream, current_time)
+            .saturating_sub(stream.total_withdrawn)
+    }
 
-        let seconds_elapsed = (elapsed / 1_000_000) as u128;
+    /// Calculate how much the stream has paid out in total, withdrawn or not
+    pub fn calculate_streamed_amount(&self, stream: &Stream, current_time: Timestamp) -> Amount {
+        stream.streamed_at(current_time)
+    }
 
-        // Total earned = rate * seconds
-        let total_earned = stream.rate_per_second.saturating_mul(seconds_elapsed);
+    /// The time the stream has accrued up to: now, or when it was paused or ended
+    pub fn effective_time(&self, stream: &Stream, current_time: Timestamp) -> Timestamp {
+        stream.effective_time(current_time)
+    }
 
-        // Available = earned - withdrawn
-        total_earned.saturating_sub(stream.total_withdrawn)
+    /// Check if stream is currently paying out
+    pub fn is_streaming(&self, stream: &Stream, current_time: Timestamp) -> bool {
+        stream.status == StreamStatus::Active
+            && !self.should_complete_stream(stream, current_time)
+            && self.calculate_streamed_amount(stream, current_time) < stream.total_deposited
     }
 
     /// Check if stream should be completed (duration expired)
@@ -77,4 +106,78 @@ impl StreamState {
             .insert(&recipient, recipient_streams)
             .unwrap();
     }
+
+    pub async fn add_subscription_to_subscriber(
+        &mut self,
+        subscriber: AccountOwner,
+        subscription_id: u64,
+    ) {
+        let mut subscriptions = self
+            .subscriptions_by_subscriber
+            .get(&subscriber)
+            .await
+            .unwrap()
+            .unwrap_or_default();
+        subscriptions.push(subscription_id);
+        self.subscriptions_by_subscriber
+            .insert(&subscriber, subscriptions)
+            .unwrap();
+    }
+
+    pub async fn add_subscription_to_merchant(
+        &mut self,
+        merchant: AccountOwner,
+        subscription_id: u64,
+    ) {
+        let mut subscriptions = self
+            .subscriptions_by_merchant
+            .get(&merchant)
+            .await
+            .unwrap()
+            .unwrap_or_default();
+        subscriptions.push(subscription_id);
+        self.subscriptions_by_merchant
+            .insert(&merchant, subscriptions)
+            .unwrap();
+    }
+
+    pub async fn is_admin(&self, owner: &AccountOwner) -> bool {
+        self.admins.contains(owner).await.unwrap()
+    }
+
+    pub async fn is_token_allowed(&self, token: &ApplicationId) -> bool {
+        self.allowed_tokens.contains(token).await.unwrap()
+    }
+
+    /// The escrow totals of `token`, or of the native token if `None`, which are zero for a
+    /// token that has never funded a stream
+    pub async fn token_totals_of(&self, token: Option<ApplicationId>) -> TokenTotals {
+        let Some(token) = token else {
+            return self.native_totals.get().clone();
+        };
+        self.token_totals
+            .get(&token)
+            .await
+            .unwrap()
+            .unwrap_or_else(|| TokenTotals {
+                token: Some(token),
+                ..TokenTotals::default()
+            })
+    }
+
+    /// Apply `update` to the escrow totals of `token`, or of the native token if `None`,
+    /// starting from zero for a new token
+    pub async fn update_token_totals(
+        &mut self,
+        token: Option<ApplicationId>,
+        update: impl FnOnce(&mut TokenTotals),
+    ) {
+        let Some(token) = token else {
+            update(self.native_totals.get_mut());
+            return;
+        };
+        let mut totals = self.token_totals_of(Some(token)).await;
+        update(&mut totals);
+        self.token_totals.insert(&token, totals).unwrap();
+    }
 }
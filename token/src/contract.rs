@@ -3,11 +3,14 @@
 mod state;
 
 use linera_sdk::{
-    linera_base_types::{Amount, WithContractAbi},
+    linera_base_types::{Account, AccountOwner, Amount, StreamName, WithContractAbi},
     views::{RootView, View},
     Contract, ContractRuntime,
 };
-use token::{DailyBonus, TokenAbi, TokenOperation, TokenResponse};
+use token::{
+    OwnerSpender, TokenAbi, TokenError, TokenEvent, TokenInstantiationArgument, TokenMessage,
+    TokenOperation, TokenResponse, TOKEN_EVENTS,
+};
 
 use self::state::TokenState;
 
@@ -23,10 +26,10 @@ impl WithContractAbi for TokenContract {
 }
 
 impl Contract for TokenContract {
-    type Message = ();
+    type Message = TokenMessage;
     type Parameters = ();
-    type InstantiationArgument = ();
-    type EventValue = ();
+    type InstantiationArgument = TokenInstantiationArgument;
+    type EventValue = TokenEvent;
 
     async fn load(runtime: ContractRuntime<Self>) -> Self {
         let state = TokenState::load(runtime.root_view_storage_context())
@@ -35,79 +38,390 @@ impl Contract for TokenContract {
         TokenContract { state, runtime }
     }
 
-    async fn instantiate(&mut self, _argument: Self::InstantiationArgument) {
-        // Initialize with some tokens for testing
-        self.state.total_supply.set(Amount::from_attos(1_000_000_000));
+    async fn instantiate(&mut self, argument: Self::InstantiationArgument) {
+        // Supply only grows through minting, so it always matches the account balances
+        self.state.total_supply.set(Amount::ZERO);
+        for allocation in argument.initial_allocations {
+            self.state
+                .mint(allocation.owner, allocation.amount)
+                .await
+                .expect("Invalid initial allocation");
+            self.record(TokenEvent::Mint {
+                target: allocation.owner,
+                amount: allocation.amount,
+            })
+            .await;
+        }
+
+        for admin in argument.admins {
+            self.state.admins.insert(&admin).unwrap();
+        }
+        for minter in argument.minters {
+            self.state.minters.insert(&minter).unwrap();
+        }
+        self.state.metadata.set(argument.metadata);
+        self.state.faucet.set(argument.faucet);
     }
 
     async fn execute_operation(&mut self, operation: Self::Operation) -> Self::Response {
+        match self.execute(operation).await {
+            Ok(response) => response,
+            Err(error) => TokenResponse::Error(error),
+        }
+    }
+
+    async fn execute_message(&mut self, message: Self::Message) {
+        // Failing here rejects the message, which bounces a tracked credit back to its sender
+        match message {
+            TokenMessage::Credit {
+                source,
+                target,
+                amount,
+                memo,
+            } => {
+                // A tracked message comes back to the sender's chain if the target rejects it
+                if self.runtime.message_is_bouncing() == Some(true) {
+                    self.state
+                        .mint(source.owner, amount)
+                        .await
+                        .expect("Failed to refund returned transfer");
+                    self.record(TokenEvent::TransferReturned {
+                        source: source.owner,
+                        amount,
+                    })
+                    .await;
+                    return;
+                }
+
+                self.state
+                    .check_can_receive(&target)
+                    .await
+                    .expect("Target account can't receive transfers");
+                self.state
+                    .mint(target, amount)
+                    .await
+                    .expect("Failed to credit incoming transfer");
+                self.record(TokenEvent::IncomingTransfer {
+                    source,
+                    target,
+                    amount,
+                    memo,
+                })
+                .await;
+            }
+        }
+    }
+
+    async fn store(mut self) {
+        self.state.save().await.expect("Failed to save state");
+    }
+}
+
+impl TokenContract {
+    async fn execute(&mut self, operation: TokenOperation) -> Result<TokenResponse, TokenError> {
         match operation {
             TokenOperation::Balance { owner } => {
-                let balance = self.state.balance(&owner).await;
-                TokenResponse::Balance(balance)
+                self.check_application_caller()?;
+                let balance = self.state.balance(&owner).await?;
+                Ok(TokenResponse::Balance(balance))
+            }
+
+            TokenOperation::Allowance { owner, spender } => {
+                self.check_application_caller()?;
+                let allowance = self
+                    .state
+                    .allowance(&OwnerSpender { owner, spender })
+                    .await?;
+                Ok(TokenResponse::Allowance(allowance))
             }
 
             TokenOperation::Transfer {
                 owner,
                 amount,
-                target_owner,
+                target_account,
+                memo,
             } => {
-                // Check permission
-                self.runtime
-                    .check_account_permission(owner)
-                    .expect("Permission denied");
-
-                // Debit sender
-                if let Err(e) = self.state.debit(owner, amount).await {
-                    return TokenResponse::Error(e);
+                self.check_permission(owner)?;
+                self.state.check_can_send(&owner).await?;
+                // Checked here for cross-chain targets too, since the target chain may not
+                // have the compliance lists this chain has
+                self.state.check_can_receive(&target_account.owner).await?;
+
+                let chain_id = self.runtime.chain_id();
+                if target_account.chain_id == chain_id {
+                    self.state
+                        .transfer(owner, target_account.owner, amount)
+                        .await?;
+
+                    self.record(TokenEvent::Transfer {
+                        source: owner,
+                        target: target_account.owner,
+                        amount,
+                        memo,
+                    })
+                    .await;
+                } else {
+                    // The tokens leave this chain's supply until the target chain credits them
+                    self.state.burn(owner, amount).await?;
+
+                    let message = TokenMessage::Credit {
+                        source: Account { chain_id, owner },
+                        target: target_account.owner,
+                        amount,
+                        memo: memo.clone(),
+                    };
+                    self.runtime
+                        .prepare_message(message)
+                        .with_authentication()
+                        .with_tracking()
+                        .send_to(target_account.chain_id);
+
+                    self.record(TokenEvent::OutgoingTransfer {
+                        source: owner,
+                        target: target_account,
+                        amount,
+                        memo,
+                    })
+                    .await;
                 }
 
-                // Credit recipient
-                self.state.credit(target_owner, amount).await;
+                Ok(TokenResponse::Ok)
+            }
+
+            TokenOperation::Approve {
+                owner,
+                spender,
+                amount,
+            } => {
+                self.check_permission(owner)?;
+
+                self.state
+                    .set_allowance(OwnerSpender { owner, spender }, amount)?;
+
+                Ok(TokenResponse::Ok)
+            }
+
+            TokenOperation::IncreaseAllowance {
+                owner,
+                spender,
+                amount,
+            } => {
+                self.check_permission(owner)?;
+
+                let owner_spender = OwnerSpender { owner, spender };
+                let allowance = self
+                    .state
+                    .allowance(&owner_spender)
+                    .await?
+                    .try_add(amount)
+                    .map_err(|_| TokenError::Overflow)?;
+                self.state.set_allowance(owner_spender, allowance)?;
+
+                Ok(TokenResponse::Ok)
+            }
+
+            TokenOperation::DecreaseAllowance {
+                owner,
+                spender,
+                amount,
+            } => {
+                self.check_permission(owner)?;
+
+                let owner_spender = OwnerSpender { owner, spender };
+                let allowance = self
+                    .state
+                    .allowance_after_spending(&owner_spender, amount)
+                    .await?;
+                self.state.set_allowance(owner_spender, allowance)?;
+
+                Ok(TokenResponse::Ok)
+            }
+
+            TokenOperation::TransferFrom {
+                spender,
+                owner,
+                amount,
+                target_owner,
+                memo,
+            } => {
+                self.check_permission(spender)?;
+                self.state.check_can_send(&owner).await?;
+                self.state.check_can_send(&spender).await?;
+                self.state.check_can_receive(&target_owner).await?;
+
+                let owner_spender = OwnerSpender { owner, spender };
+                let allowance = self
+                    .state
+                    .allowance_after_spending(&owner_spender, amount)
+                    .await?;
+                self.state.transfer(owner, target_owner, amount).await?;
+                self.state.set_allowance(owner_spender, allowance)?;
+
+                self.record(TokenEvent::Transfer {
+                    source: owner,
+                    target: target_owner,
+                    amount,
+                    memo,
+                })
+                .await;
 
-                TokenResponse::Ok
+                Ok(TokenResponse::Ok)
             }
 
             TokenOperation::ClaimBonus { owner } => {
-                // Check permission
-                self.runtime
-                    .check_account_permission(owner)
-                    .expect("Permission denied");
+                self.check_permission(owner)?;
+                self.state.check_can_receive(&owner).await?;
 
                 let current_time = self.runtime.system_time();
+                let claimed_amount = self.state.claim_faucet(owner, current_time).await?;
 
-                // Get or create daily bonus
-                let mut bonus = self
-                    .state
-                    .daily_bonuses
-                    .get(&owner)
-                    .await
-                    .unwrap()
-                    .unwrap_or_else(|| DailyBonus::new(Amount::from_attos(1_000_000)));
+                self.record(TokenEvent::FaucetClaimed {
+                    target: owner,
+                    amount: claimed_amount,
+                })
+                .await;
+
+                Ok(TokenResponse::Balance(claimed_amount))
+            }
+
+            TokenOperation::FundFaucet { owner, amount } => {
+                self.check_permission(owner)?;
+                self.state.check_can_send(&owner).await?;
+
+                self.state.fund_faucet(owner, amount).await?;
+
+                self.record(TokenEvent::FaucetFunded {
+                    source: owner,
+                    amount,
+                })
+                .await;
+
+                Ok(TokenResponse::Ok)
+            }
+
+            TokenOperation::ConfigureFaucet { admin, config } => {
+                self.check_admin(admin).await?;
 
-                let claimed_amount = bonus.claim(current_time);
+                self.state.faucet.set(config);
 
-                if claimed_amount == Amount::ZERO {
-                    return TokenResponse::Error("Bonus not available yet".to_string());
+                Ok(TokenResponse::Ok)
+            }
+
+            TokenOperation::FreezeAccount { admin, account } => {
+                self.check_admin(admin).await?;
+                self.state.frozen.insert(&account)?;
+                Ok(TokenResponse::Ok)
+            }
+
+            TokenOperation::UnfreezeAccount { admin, account } => {
+                self.check_admin(admin).await?;
+                self.state.frozen.remove(&account)?;
+                Ok(TokenResponse::Ok)
+            }
+
+            TokenOperation::AddToDenylist { admin, account } => {
+                self.check_admin(admin).await?;
+                self.state.denylist.insert(&account)?;
+                Ok(TokenResponse::Ok)
+            }
+
+            TokenOperation::RemoveFromDenylist { admin, account } => {
+                self.check_admin(admin).await?;
+                self.state.denylist.remove(&account)?;
+                Ok(TokenResponse::Ok)
+            }
+
+            TokenOperation::SetTransfersPaused { admin, paused } => {
+                self.check_admin(admin).await?;
+                self.state.transfers_paused.set(paused);
+                Ok(TokenResponse::Ok)
+            }
+
+            TokenOperation::Mint {
+                minter,
+                amount,
+                target_owner,
+            } => {
+                self.check_permission(minter)?;
+
+                if !self.state.is_minter(&minter).await? {
+                    return Err(TokenError::NotMinter);
                 }
+                self.state.check_can_receive(&target_owner).await?;
 
-                // Update bonus
-                self.state
-                    .daily_bonuses
-                    .insert(&owner, bonus)
-                    .unwrap();
+                self.state.mint(target_owner, amount).await?;
 
-                // Credit account
-                self.state.credit(owner, claimed_amount).await;
+                self.record(TokenEvent::Mint {
+                    target: target_owner,
+                    amount,
+                })
+                .await;
 
-                TokenResponse::Balance(claimed_amount)
+                Ok(TokenResponse::Ok)
+            }
+
+            TokenOperation::Burn { owner, amount } => {
+                self.check_permission(owner)?;
+
+                if !self.state.is_minter(&owner).await? {
+                    return Err(TokenError::NotMinter);
+                }
+                self.state.check_can_send(&owner).await?;
+
+                self.state.burn(owner, amount).await?;
+
+                self.record(TokenEvent::Burn {
+                    source: owner,
+                    amount,
+                })
+                .await;
+
+                Ok(TokenResponse::Ok)
             }
         }
     }
 
-    async fn execute_message(&mut self, _message: Self::Message) {}
+    /// Check that the operation was signed by `owner`, or called by `owner` if it's an
+    /// application
+    fn check_permission(&mut self, owner: AccountOwner) -> Result<(), TokenError> {
+        self.runtime
+            .check_account_permission(owner)
+            .map_err(|_| TokenError::PermissionDenied)
+    }
 
-    async fn store(mut self) {
-        self.state.save().await.expect("Failed to save state");
+    /// Check that the operation is a call from another application rather than a block
+    /// operation, for reads that would otherwise only clutter blocks
+    fn check_application_caller(&mut self) -> Result<(), TokenError> {
+        if self.runtime.authenticated_caller_id().is_none() {
+            return Err(TokenError::ApplicationOnly);
+        }
+        Ok(())
+    }
+
+    /// Check that the operation was authorized by `admin` and that it is an admin
+    async fn check_admin(&mut self, admin: AccountOwner) -> Result<(), TokenError> {
+        self.check_permission(admin)?;
+        if !self.state.is_admin(&admin).await? {
+            return Err(TokenError::NotAdmin);
+        }
+        Ok(())
+    }
+
+    /// Emit a token event and append it to the ledgers of the accounts it affects.
+    ///
+    /// This runs after the balances have changed, so a storage failure panics to abort the
+    /// whole transaction instead of leaving a balance movement without its ledger entry.
+    async fn record(&mut self, event: TokenEvent) {
+        let timestamp = self.runtime.system_time();
+        let application = self.runtime.authenticated_caller_id();
+        for (owner, entry) in event.ledger_entries(timestamp, application) {
+            self.state
+                .ledger
+                .load_entry_mut(&owner)
+                .await
+                .expect("Failed to record token event")
+                .push(entry);
+        }
+        self.runtime.emit(StreamName::from(TOKEN_EVENTS), &event);
     }
 }
@@ -1,9 +1,13 @@
-use async_graphql::{Request, Response, SimpleObject};
+use async_graphql::{Enum, InputObject, Request, Response, SimpleObject};
 use linera_sdk::{
     graphql::GraphQLMutationRoot,
-    linera_base_types::{AccountOwner, Amount, ContractAbi, ServiceAbi, Timestamp},
+    linera_base_types::{
+        Account, AccountOwner, Amount, ApplicationId, ChainId, ContractAbi, ServiceAbi, Timestamp,
+    },
+    views::ViewError,
 };
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct TokenAbi;
@@ -20,52 +24,649 @@ impl ServiceAbi for TokenAbi {
 
 #[derive(Debug, Deserialize, Serialize, GraphQLMutationRoot)]
 pub enum TokenOperation {
-    /// Transfer tokens
+    /// Transfer tokens, to an account on this chain or on another one
     Transfer {
         owner: AccountOwner,
         amount: Amount,
-        target_owner: AccountOwner,
+        target_account: Account,
+        memo: Option<String>,
     },
 
-    /// Claim daily bonus
+    /// Claim from the faucet
     ClaimBonus { owner: AccountOwner },
 
-    /// Check balance
+    /// Move tokens from the owner's balance into the faucet reserve
+    FundFaucet { owner: AccountOwner, amount: Amount },
+
+    /// Replace the faucet policy (admins only)
+    ConfigureFaucet {
+        admin: AccountOwner,
+        config: FaucetConfig,
+    },
+
+    /// Block outgoing transfers from an account (admins only). Compliance controls only
+    /// apply on the chain that created the token, where the admins are; tokens an account
+    /// holds on other chains stay movable there.
+    FreezeAccount {
+        admin: AccountOwner,
+        account: AccountOwner,
+    },
+
+    /// Lift a freeze (admins only)
+    UnfreezeAccount {
+        admin: AccountOwner,
+        account: AccountOwner,
+    },
+
+    /// Block an account from sending, receiving, minting and claiming (admins only)
+    AddToDenylist {
+        admin: AccountOwner,
+        account: AccountOwner,
+    },
+
+    /// Remove an account from the denylist (admins only)
+    RemoveFromDenylist {
+        admin: AccountOwner,
+        account: AccountOwner,
+    },
+
+    /// Pause or resume all outgoing transfers (admins only)
+    SetTransfersPaused { admin: AccountOwner, paused: bool },
+
+    /// Mint new tokens to a target account (minters only)
+    Mint {
+        minter: AccountOwner,
+        amount: Amount,
+        target_owner: AccountOwner,
+    },
+
+    /// Burn tokens from the minter's own balance (minters only)
+    Burn { owner: AccountOwner, amount: Amount },
+
+    /// Set how much a spender may transfer out of the owner's account
+    Approve {
+        owner: AccountOwner,
+        spender: AccountOwner,
+        amount: Amount,
+    },
+
+    /// Raise a spender's allowance
+    IncreaseAllowance {
+        owner: AccountOwner,
+        spender: AccountOwner,
+        amount: Amount,
+    },
+
+    /// Lower a spender's allowance
+    DecreaseAllowance {
+        owner: AccountOwner,
+        spender: AccountOwner,
+        amount: Amount,
+    },
+
+    /// Transfer tokens out of the owner's account using the spender's allowance
+    TransferFrom {
+        spender: AccountOwner,
+        owner: AccountOwner,
+        amount: Amount,
+        target_owner: AccountOwner,
+        memo: Option<String>,
+    },
+
+    /// Read a balance without changing any state. Only other applications may call this,
+    /// through `call_application`; clients should use the service's `balance` query.
     Balance { owner: AccountOwner },
+
+    /// Read an allowance without changing any state. Only other applications may call this,
+    /// through `call_application`; clients should use the service's `allowance` query.
+    Allowance {
+        owner: AccountOwner,
+        spender: AccountOwner,
+    },
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub enum TokenResponse {
     Ok,
     Balance(Amount),
-    Error(String),
+    Allowance(Amount),
+    Error(TokenError),
+}
+
+/// Why a token operation was rejected. Returned in [`TokenResponse::Error`] instead of
+/// panicking, so one failed operation doesn't fail the rest of its block.
+#[derive(Clone, Debug, Deserialize, Eq, Error, PartialEq, Serialize)]
+pub enum TokenError {
+    #[error("Permission denied")]
+    PermissionDenied,
+    #[error("Insufficient balance")]
+    InsufficientBalance,
+    #[error("Insufficient allowance")]
+    InsufficientAllowance,
+    #[error("Not a minter")]
+    NotMinter,
+    #[error("Not an admin")]
+    NotAdmin,
+    #[error("Only other applications can call this")]
+    ApplicationOnly,
+    #[error("Transfers are paused")]
+    TransfersPaused,
+    #[error("Account is frozen")]
+    AccountFrozen,
+    #[error("Account is denylisted")]
+    AccountDenied,
+    #[error("Bonus not available yet")]
+    BonusNotAvailable,
+    #[error("Faucet is disabled")]
+    FaucetDisabled,
+    #[error("Faucet lifetime cap reached for this account")]
+    FaucetCapReached,
+    #[error("Faucet budget exhausted")]
+    FaucetBudgetExhausted,
+    #[error("Faucet reserve is empty")]
+    FaucetReserveEmpty,
+    #[error("Arithmetic overflow")]
+    Overflow,
+    #[error("Storage error: {0}")]
+    Storage(String),
+}
+
+impl From<ViewError> for TokenError {
+    fn from(error: ViewError) -> Self {
+        TokenError::Storage(error.to_string())
+    }
+}
+
+/// Key of the allowance map: how much `spender` may move out of `owner`'s account.
+#[derive(
+    Clone, Debug, Deserialize, Eq, InputObject, Ord, PartialEq, PartialOrd, Serialize, SimpleObject,
+)]
+#[graphql(input_name = "OwnerSpenderInput")]
+pub struct OwnerSpender {
+    pub owner: AccountOwner,
+    pub spender: AccountOwner,
+}
+
+/// Argument of `linera publish-and-create` for the token application.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TokenInstantiationArgument {
+    pub metadata: TokenMetadata,
+    /// Balances credited at instantiation. The initial total supply is their sum.
+    pub initial_allocations: Vec<InitialAllocation>,
+    /// Accounts allowed to administer the token.
+    pub admins: Vec<AccountOwner>,
+    /// Accounts allowed to mint and burn.
+    pub minters: Vec<AccountOwner>,
+    pub faucet: FaucetConfig,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InitialAllocation {
+    pub owner: AccountOwner,
+    pub amount: Amount,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
-pub struct DailyBonus {
+pub struct TokenMetadata {
+    pub name: String,
+    pub symbol: String,
+    /// Decimal places shown to users. Balances are always stored with `Amount`'s precision.
+    pub decimals: u8,
+    pub icon_uri: Option<String>,
+}
+
+impl Default for TokenMetadata {
+    fn default() -> Self {
+        Self {
+            name: "StreamPay Token".to_string(),
+            symbol: "SPT".to_string(),
+            decimals: Amount::DECIMAL_PLACES,
+            icon_uri: None,
+        }
+    }
+}
+
+impl TokenMetadata {
+    /// Format an amount with this token's decimals and symbol, e.g. `12.50 SPT`
+    pub fn format(&self, amount: Amount) -> String {
+        let attos = u128::from(amount);
+        let decimals = self.decimals.min(Amount::DECIMAL_PLACES);
+        let one_token = 10u128.pow(u32::from(Amount::DECIMAL_PLACES));
+        let whole = attos / one_token;
+        if decimals == 0 {
+            return format!("{} {}", whole, self.symbol);
+        }
+        let fraction = attos % one_token / 10u128.pow(u32::from(Amount::DECIMAL_PLACES - decimals));
+        format!(
+            "{}.{:0width$} {}",
+            whole,
+            fraction,
+            self.symbol,
+            width = usize::from(decimals)
+        )
+    }
+}
+
+/// Name of the event stream the token application emits [`TokenEvent`]s on.
+pub const TOKEN_EVENTS: &[u8] = b"token_events";
+
+/// Emitted for every balance movement, so indexers can rebuild the ledger.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum TokenEvent {
+    Transfer {
+        source: AccountOwner,
+        target: AccountOwner,
+        amount: Amount,
+        memo: Option<String>,
+    },
+    Mint {
+        target: AccountOwner,
+        amount: Amount,
+    },
+    Burn {
+        source: AccountOwner,
+        amount: Amount,
+    },
+    /// Tokens left this chain for an account on another chain.
+    OutgoingTransfer {
+        source: AccountOwner,
+        target: Account,
+        amount: Amount,
+        memo: Option<String>,
+    },
+    /// Tokens from another chain were credited on this chain.
+    IncomingTransfer {
+        source: Account,
+        target: AccountOwner,
+        amount: Amount,
+        memo: Option<String>,
+    },
+    /// An outgoing transfer was rejected by the target chain and refunded.
+    TransferReturned {
+        source: AccountOwner,
+        amount: Amount,
+    },
+    /// An account moved tokens into the faucet reserve.
+    FaucetFunded {
+        source: AccountOwner,
+        amount: Amount,
+    },
+    /// An account claimed tokens from the faucet reserve.
+    FaucetClaimed {
+        target: AccountOwner,
+        amount: Amount,
+    },
+}
+
+impl TokenEvent {
+    /// The ledger entries this event adds, one per affected account on this chain
+    pub fn ledger_entries(
+        &self,
+        timestamp: Timestamp,
+        application: Option<ApplicationId>,
+    ) -> Vec<(AccountOwner, LedgerEntry)> {
+        // Each side is (account, counterparty, counterparty's chain if it isn't this one)
+        let (kind, debited, credited, amount, memo) = match self.clone() {
+            TokenEvent::Transfer {
+                source,
+                target,
+                amount,
+                memo,
+            } => (
+                LedgerEntryKind::Transfer,
+                Some((source, Some(target), None)),
+                Some((target, Some(source), None)),
+                amount,
+                memo,
+            ),
+            TokenEvent::Mint { target, amount } => (
+                LedgerEntryKind::Mint,
+                None,
+                Some((target, None, None)),
+                amount,
+                None,
+            ),
+            TokenEvent::Burn { source, amount } => (
+                LedgerEntryKind::Burn,
+                Some((source, None, None)),
+                None,
+                amount,
+                None,
+            ),
+            TokenEvent::OutgoingTransfer {
+                source,
+                target,
+                amount,
+                memo,
+            } => (
+                LedgerEntryKind::Transfer,
+                Some((source, Some(target.owner), Some(target.chain_id))),
+                None,
+                amount,
+                memo,
+            ),
+            TokenEvent::IncomingTransfer {
+                source,
+                target,
+                amount,
+                memo,
+            } => (
+                LedgerEntryKind::Transfer,
+                None,
+                Some((target, Some(source.owner), Some(source.chain_id))),
+                amount,
+                memo,
+            ),
+            TokenEvent::TransferReturned { source, amount } => (
+                LedgerEntryKind::Refund,
+                None,
+                Some((source, None, None)),
+                amount,
+                None,
+            ),
+            TokenEvent::FaucetFunded { source, amount } => (
+                LedgerEntryKind::Faucet,
+                Some((source, None, None)),
+                None,
+                amount,
+                None,
+            ),
+            TokenEvent::FaucetClaimed { target, amount } => (
+                LedgerEntryKind::Faucet,
+                None,
+                Some((target, None, None)),
+                amount,
+                None,
+            ),
+        };
+
+        [
+            (LedgerDirection::Debit, debited),
+            (LedgerDirection::Credit, credited),
+        ]
+        .into_iter()
+        .filter_map(|(direction, side)| {
+            let (owner, counterparty, counterparty_chain) = side?;
+            let entry = LedgerEntry {
+                kind,
+                direction,
+                counterparty,
+                counterparty_chain,
+                amount,
+                memo: memo.clone(),
+                timestamp,
+                application,
+            };
+            Some((owner, entry))
+        })
+        .collect()
+    }
+}
+
+/// A credit or debit in an account's history.
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct LedgerEntry {
+    pub kind: LedgerEntryKind,
+    pub direction: LedgerDirection,
+    /// The other side of a transfer, if any.
+    pub counterparty: Option<AccountOwner>,
+    /// Chain of the counterparty, if it isn't this chain.
+    pub counterparty_chain: Option<ChainId>,
     pub amount: Amount,
+    pub memo: Option<String>,
+    pub timestamp: Timestamp,
+    /// Application that requested the movement, e.g. the stream app paying out a withdrawal.
+    pub application: Option<ApplicationId>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Enum, Eq, PartialEq, Serialize)]
+pub enum LedgerEntryKind {
+    Transfer,
+    Mint,
+    Burn,
+    Faucet,
+    Refund,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Enum, Eq, PartialEq, Serialize)]
+pub enum LedgerDirection {
+    Credit,
+    Debit,
+}
+
+/// Messages the token application sends between chains.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum TokenMessage {
+    /// Credit tokens debited from `source` on its chain. Bounces back to refund `source`.
+    Credit {
+        source: Account,
+        target: AccountOwner,
+        amount: Amount,
+        memo: Option<String>,
+    },
+}
+
+/// Faucet claims of one account.
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct DailyBonus {
     pub last_claim: Timestamp,
+    /// Claimed over the account's lifetime.
+    pub total_claimed: Amount,
 }
 
-impl DailyBonus {
-    pub fn new(amount: Amount) -> Self {
+impl Default for DailyBonus {
+    fn default() -> Self {
         Self {
-            amount,
             last_claim: Timestamp::from(0),
+            total_claimed: Amount::ZERO,
         }
     }
+}
 
-    pub fn can_claim(&self, current_time: Timestamp) -> bool {
+impl DailyBonus {
+    pub fn can_claim(&self, current_time: Timestamp, cooldown_seconds: u64) -> bool {
         let elapsed = current_time.delta_since(self.last_claim).as_micros();
-        elapsed >= 86_400_000_000 // 24 hours in microseconds
+        elapsed >= cooldown_seconds.saturating_mul(1_000_000)
     }
+}
+
+/// Faucet policy, set by an admin. Claims are paid from the faucet reserve, never minted.
+#[derive(Clone, Debug, Deserialize, InputObject, Serialize, SimpleObject)]
+#[graphql(input_name = "FaucetConfigInput")]
+pub struct FaucetConfig {
+    pub enabled: bool,
+    /// Paid out per claim.
+    pub amount: Amount,
+    /// Minimum time between two claims by the same account.
+    pub cooldown_seconds: u64,
+    /// Most a single account may ever claim.
+    pub lifetime_cap: Option<Amount>,
+    /// Most the faucet may ever pay out across all accounts.
+    pub global_budget: Option<Amount>,
+}
 
-    pub fn claim(&mut self, current_time: Timestamp) -> Amount {
-        if self.can_claim(current_time) {
-            self.last_claim = current_time;
-            self.amount
-        } else {
-            Amount::ZERO
+impl Default for FaucetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            amount: Amount::from_attos(1_000_000),
+            cooldown_seconds: 86_400, // 24 hours
+            lifetime_cap: None,
+            global_budget: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use linera_sdk::linera_base_types::{
+        Account, AccountOwner, Amount, ChainId, CryptoHash, Timestamp,
+    };
+
+    use super::{LedgerDirection, LedgerEntryKind, TokenEvent, TokenMetadata};
+
+    const ALICE: AccountOwner = AccountOwner::Address20([1; 20]);
+    const BOB: AccountOwner = AccountOwner::Address20([2; 20]);
+
+    fn other_chain() -> ChainId {
+        ChainId(CryptoHash::from([7; 4]))
+    }
+
+    /// Each entry as (account, direction, counterparty, counterparty's chain)
+    fn sides(
+        event: TokenEvent,
+    ) -> Vec<(
+        AccountOwner,
+        LedgerDirection,
+        Option<AccountOwner>,
+        Option<ChainId>,
+    )> {
+        event
+            .ledger_entries(Timestamp::from(5), None)
+            .into_iter()
+            .map(|(owner, entry)| {
+                assert_eq!(entry.amount, Amount::ONE);
+                assert_eq!(entry.timestamp, Timestamp::from(5));
+                (
+                    owner,
+                    entry.direction,
+                    entry.counterparty,
+                    entry.counterparty_chain,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn transfers_debit_the_source_and_credit_the_target() {
+        let event = TokenEvent::Transfer {
+            source: ALICE,
+            target: BOB,
+            amount: Amount::ONE,
+            memo: Some("rent".to_string()),
+        };
+        let entries = event.ledger_entries(Timestamp::from(5), None);
+        assert!(entries.iter().all(|(_, entry)| {
+            entry.kind == LedgerEntryKind::Transfer && entry.memo.as_deref() == Some("rent")
+        }));
+        assert_eq!(
+            sides(event),
+            vec![
+                (ALICE, LedgerDirection::Debit, Some(BOB), None),
+                (BOB, LedgerDirection::Credit, Some(ALICE), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn cross_chain_transfers_name_the_counterparty_chain() {
+        let outgoing = TokenEvent::OutgoingTransfer {
+            source: ALICE,
+            target: Account {
+                chain_id: other_chain(),
+                owner: BOB,
+            },
+            amount: Amount::ONE,
+            memo: None,
+        };
+        assert_eq!(
+            sides(outgoing),
+            vec![(
+                ALICE,
+                LedgerDirection::Debit,
+                Some(BOB),
+                Some(other_chain())
+            )]
+        );
+
+        let incoming = TokenEvent::IncomingTransfer {
+            source: Account {
+                chain_id: other_chain(),
+                owner: ALICE,
+            },
+            target: BOB,
+            amount: Amount::ONE,
+            memo: None,
+        };
+        assert_eq!(
+            sides(incoming),
+            vec![(
+                BOB,
+                LedgerDirection::Credit,
+                Some(ALICE),
+                Some(other_chain())
+            )]
+        );
+    }
+
+    #[test]
+    fn supply_and_faucet_events_have_one_side() {
+        let credited = [
+            TokenEvent::Mint {
+                target: ALICE,
+                amount: Amount::ONE,
+            },
+            TokenEvent::FaucetClaimed {
+                target: ALICE,
+                amount: Amount::ONE,
+            },
+            TokenEvent::TransferReturned {
+                source: ALICE,
+                amount: Amount::ONE,
+            },
+        ];
+        for event in credited {
+            assert_eq!(
+                sides(event),
+                vec![(ALICE, LedgerDirection::Credit, None, None)]
+            );
+        }
+
+        let debited = [
+            TokenEvent::Burn {
+                source: ALICE,
+                amount: Amount::ONE,
+            },
+            TokenEvent::FaucetFunded {
+                source: ALICE,
+                amount: Amount::ONE,
+            },
+        ];
+        for event in debited {
+            assert_eq!(
+                sides(event),
+                vec![(ALICE, LedgerDirection::Debit, None, None)]
+            );
+        }
+    }
+
+    fn metadata(decimals: u8) -> TokenMetadata {
+        TokenMetadata {
+            decimals,
+            ..TokenMetadata::default()
+        }
+    }
+
+    #[test]
+    fn formats_with_the_token_decimals() {
+        let amount = Amount::from_millis(12_500);
+        assert_eq!(metadata(2).format(amount), "12.50 SPT");
+        assert_eq!(metadata(0).format(amount), "12 SPT");
+        assert_eq!(metadata(18).format(amount), "12.500000000000000000 SPT");
+    }
+
+    #[test]
+    fn truncates_digits_beyond_the_decimals() {
+        assert_eq!(metadata(2).format(Amount::from_millis(999)), "0.99 SPT");
+        assert_eq!(metadata(2).format(Amount::from_attos(1)), "0.00 SPT");
+    }
+
+    #[test]
+    fn caps_decimals_at_amount_precision() {
+        assert_eq!(
+            metadata(30).format(Amount::from_attos(1)),
+            "0.000000000000000001 SPT"
+        );
+    }
+}
@@ -4,18 +4,26 @@ mod state;
 
 use std::sync::Arc;
 
-use async_graphql::{EmptySubscription, Object, Request, Response, Schema};
+use async_graphql::{
+    connection::{query, Connection, Edge},
+    EmptySubscription, Object, Request, Response, Schema,
+};
 use linera_sdk::{
     graphql::GraphQLMutationRoot,
-    linera_base_types::{AccountOwner, WithServiceAbi},
+    linera_base_types::{AccountOwner, Amount, WithServiceAbi},
     views::View,
     Service, ServiceRuntime,
 };
 
-use token::{TokenAbi, TokenOperation};
+use token::{FaucetConfig, LedgerEntry, OwnerSpender, TokenAbi, TokenMetadata, TokenOperation};
 
 use self::state::TokenState;
 
+/// Page size used when a connection query doesn't specify `first`.
+const DEFAULT_PAGE_SIZE: usize = 20;
+/// Upper bound on `first` so a single query can't load a whole ledger.
+const MAX_PAGE_SIZE: usize = 100;
+
 #[derive(Clone)]
 pub struct TokenService {
     state: Arc<TokenState>,
@@ -54,19 +62,99 @@ impl Service for TokenService {
 
 #[Object]
 impl TokenService {
-    async fn balance(&self, owner: String) -> String {
-        let account_owner: AccountOwner = match owner.parse() {
-            Ok(o) => o,
-            Err(_) => return "0".to_string(),
-        };
-
-        match self.state.accounts.get(&account_owner).await {
-            Ok(Some(balance)) => balance.to_string(),
-            _ => "0".to_string(),
-        }
+    async fn balance(&self, owner: AccountOwner) -> async_graphql::Result<Amount> {
+        Ok(self.state.balance(&owner).await?)
+    }
+
+    async fn allowance(
+        &self,
+        owner: AccountOwner,
+        spender: AccountOwner,
+    ) -> async_graphql::Result<Amount> {
+        Ok(self
+            .state
+            .allowance(&OwnerSpender { owner, spender })
+            .await?)
+    }
+
+    /// Credits and debits of an account, oldest first, as a cursor-paginated connection.
+    async fn ledger(
+        &self,
+        owner: AccountOwner,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> async_graphql::Result<Connection<u64, LedgerEntry>> {
+        query(
+            after,
+            None,
+            first,
+            None,
+            |after: Option<u64>, _before, first, _last| async move {
+                let limit = first.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+                let Some(ledger) = self.state.ledger.try_load_entry(&owner).await? else {
+                    return Ok(Connection::new(false, false));
+                };
+                let count = ledger.count();
+                let start = after.map_or(0, |cursor| cursor as usize + 1).min(count);
+                let end = count.min(start + limit);
+                let entries = ledger.read(start..end).await?;
+
+                let mut connection = Connection::new(start > 0, end < count);
+                connection.edges.extend(
+                    entries
+                        .into_iter()
+                        .enumerate()
+                        .map(|(offset, entry)| Edge::new((start + offset) as u64, entry)),
+                );
+                Ok::<_, async_graphql::Error>(connection)
+            },
+        )
+        .await
+    }
+
+    /// Balance formatted with the token's decimals and symbol, e.g. `12.50 SPT`
+    async fn formatted_balance(&self, owner: AccountOwner) -> async_graphql::Result<String> {
+        let balance = self.state.balance(&owner).await?;
+        Ok(self.state.metadata.get().format(balance))
+    }
+
+    async fn metadata(&self) -> TokenMetadata {
+        self.state.metadata.get().clone()
+    }
+
+    async fn total_supply(&self) -> Amount {
+        *self.state.total_supply.get()
+    }
+
+    async fn faucet_config(&self) -> FaucetConfig {
+        self.state.faucet.get().clone()
+    }
+
+    async fn faucet_reserve(&self) -> Amount {
+        *self.state.faucet_reserve.get()
+    }
+
+    async fn faucet_distributed(&self) -> Amount {
+        *self.state.faucet_distributed.get()
+    }
+
+    async fn transfers_paused(&self) -> bool {
+        *self.state.transfers_paused.get()
+    }
+
+    async fn frozen_accounts(&self) -> Vec<AccountOwner> {
+        self.state.frozen.indices().await.unwrap_or_default()
+    }
+
+    async fn denylist(&self) -> Vec<AccountOwner> {
+        self.state.denylist.indices().await.unwrap_or_default()
+    }
+
+    async fn minters(&self) -> Vec<AccountOwner> {
+        self.state.minters.indices().await.unwrap_or_default()
     }
 
-    async fn total_supply(&self) -> String {
-        self.state.total_supply.get().to_string()
+    async fn admins(&self) -> Vec<AccountOwner> {
+        self.state.admins.indices().await.unwrap_or_default()
     }
 }
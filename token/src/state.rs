@@ -1,40 +1,240 @@
 use async_graphql::SimpleObject;
 use linera_sdk::{
-    linera_base_types::{AccountOwner, Amount},
-    views::{linera_views, MapView, RegisterView, RootView, ViewStorageContext},
+    linera_base_types::{AccountOwner, Amount, Timestamp},
+    views::{
+        linera_views, CollectionView, LogView, MapView, RegisterView, RootView, SetView,
+        ViewStorageContext,
+    },
 };
-use token::DailyBonus;
+use token::{DailyBonus, FaucetConfig, LedgerEntry, OwnerSpender, TokenError, TokenMetadata};
 
 #[derive(RootView, SimpleObject)]
 #[view(context = ViewStorageContext)]
 pub struct TokenState {
     pub accounts: MapView<AccountOwner, Amount>,
     pub daily_bonuses: MapView<AccountOwner, DailyBonus>,
+    /// Always equal to the sum of `accounts` and `faucet_reserve` on this chain. Tokens in
+    /// transit to another chain count towards neither.
     pub total_supply: RegisterView<Amount>,
+    pub minters: SetView<AccountOwner>,
+    pub admins: SetView<AccountOwner>,
+    pub metadata: RegisterView<TokenMetadata>,
+    pub allowances: MapView<OwnerSpender, Amount>,
+    pub faucet: RegisterView<FaucetConfig>,
+    /// Tokens set aside to pay faucet claims.
+    pub faucet_reserve: RegisterView<Amount>,
+    /// Paid out by the faucet so far, counted against `FaucetConfig::global_budget`.
+    pub faucet_distributed: RegisterView<Amount>,
+    /// Accounts that can't move tokens out. Like `denylist` and `transfers_paused`, this only
+    /// applies on this chain: admins exist on the creator chain alone, and the lists aren't
+    /// copied to other chains.
+    pub frozen: SetView<AccountOwner>,
+    /// Accounts that can't send, receive, mint or claim.
+    pub denylist: SetView<AccountOwner>,
+    pub transfers_paused: RegisterView<bool>,
+    /// Credits and debits of each account, oldest first.
+    pub ledger: CollectionView<AccountOwner, LogView<LedgerEntry>>,
 }
 
+// Every method checks all of its preconditions before writing anything, so a method that
+// returns an error leaves the state untouched.
 impl TokenState {
-    pub async fn balance(&self, owner: &AccountOwner) -> Amount {
-        self.accounts
-            .get(owner)
-            .await
-            .unwrap()
-            .unwrap_or(Amount::ZERO)
+    pub async fn balance(&self, owner: &AccountOwner) -> Result<Amount, TokenError> {
+        Ok(self.accounts.get(owner).await?.unwrap_or(Amount::ZERO))
     }
 
-    pub async fn credit(&mut self, owner: AccountOwner, amount: Amount) {
-        let mut balance = self.balance(&owner).await;
-        balance = balance.saturating_add(amount);
-        self.accounts.insert(&owner, balance).unwrap();
+    pub async fn credit(&mut self, owner: AccountOwner, amount: Amount) -> Result<(), TokenError> {
+        let balance = self
+            .balance(&owner)
+            .await?
+            .try_add(amount)
+            .map_err(|_| TokenError::Overflow)?;
+        self.accounts.insert(&owner, balance)?;
+        Ok(())
+    }
+
+    pub async fn debit(&mut self, owner: AccountOwner, amount: Amount) -> Result<(), TokenError> {
+        let balance = self
+            .balance(&owner)
+            .await?
+            .try_sub(amount)
+            .map_err(|_| TokenError::InsufficientBalance)?;
+        self.accounts.insert(&owner, balance)?;
+        Ok(())
+    }
+
+    /// Move tokens between two accounts on this chain
+    pub async fn transfer(
+        &mut self,
+        source: AccountOwner,
+        target: AccountOwner,
+        amount: Amount,
+    ) -> Result<(), TokenError> {
+        let source_balance = self
+            .balance(&source)
+            .await?
+            .try_sub(amount)
+            .map_err(|_| TokenError::InsufficientBalance)?;
+        if source == target {
+            return Ok(());
+        }
+        let target_balance = self
+            .balance(&target)
+            .await?
+            .try_add(amount)
+            .map_err(|_| TokenError::Overflow)?;
+        self.accounts.insert(&source, source_balance)?;
+        self.accounts.insert(&target, target_balance)?;
+        Ok(())
+    }
+
+    pub async fn is_minter(&self, owner: &AccountOwner) -> Result<bool, TokenError> {
+        Ok(self.minters.contains(owner).await?)
+    }
+
+    /// Create tokens on this chain, growing its total supply. Also used to receive tokens
+    /// from other chains.
+    pub async fn mint(&mut self, owner: AccountOwner, amount: Amount) -> Result<(), TokenError> {
+        let supply = self
+            .total_supply
+            .get()
+            .try_add(amount)
+            .map_err(|_| TokenError::Overflow)?;
+        self.credit(owner, amount).await?;
+        self.total_supply.set(supply);
+        Ok(())
+    }
+
+    /// Destroy tokens on this chain, shrinking its total supply. Also used to send tokens
+    /// to other chains.
+    pub async fn burn(&mut self, owner: AccountOwner, amount: Amount) -> Result<(), TokenError> {
+        self.debit(owner, amount).await?;
+        let supply = self.total_supply.get().saturating_sub(amount);
+        self.total_supply.set(supply);
+        Ok(())
+    }
+
+    pub async fn allowance(&self, owner_spender: &OwnerSpender) -> Result<Amount, TokenError> {
+        Ok(self
+            .allowances
+            .get(owner_spender)
+            .await?
+            .unwrap_or(Amount::ZERO))
+    }
+
+    pub fn set_allowance(
+        &mut self,
+        owner_spender: OwnerSpender,
+        amount: Amount,
+    ) -> Result<(), TokenError> {
+        if amount == Amount::ZERO {
+            self.allowances.remove(&owner_spender)?;
+        } else {
+            self.allowances.insert(&owner_spender, amount)?;
+        }
+        Ok(())
     }
 
-    pub async fn debit(&mut self, owner: AccountOwner, amount: Amount) -> Result<(), String> {
-        let balance = self.balance(&owner).await;
-        if balance < amount {
-            return Err("Insufficient balance".to_string());
+    /// Remaining allowance after spending `amount` of it, failing if it doesn't cover the
+    /// amount. Doesn't write anything.
+    pub async fn allowance_after_spending(
+        &self,
+        owner_spender: &OwnerSpender,
+        amount: Amount,
+    ) -> Result<Amount, TokenError> {
+        self.allowance(owner_spender)
+            .await?
+            .try_sub(amount)
+            .map_err(|_| TokenError::InsufficientAllowance)
+    }
+
+    pub async fn is_admin(&self, owner: &AccountOwner) -> Result<bool, TokenError> {
+        Ok(self.admins.contains(owner).await?)
+    }
+
+    /// Check that tokens may leave the account
+    pub async fn check_can_send(&self, owner: &AccountOwner) -> Result<(), TokenError> {
+        if *self.transfers_paused.get() {
+            return Err(TokenError::TransfersPaused);
+        }
+        if self.frozen.contains(owner).await? {
+            return Err(TokenError::AccountFrozen);
         }
-        let new_balance = balance.saturating_sub(amount);
-        self.accounts.insert(&owner, new_balance).unwrap();
+        self.check_can_receive(owner).await
+    }
+
+    /// Check that tokens may arrive in the account
+    pub async fn check_can_receive(&self, owner: &AccountOwner) -> Result<(), TokenError> {
+        if self.denylist.contains(owner).await? {
+            return Err(TokenError::AccountDenied);
+        }
+        Ok(())
+    }
+
+    /// Move tokens from an account into the faucet reserve
+    pub async fn fund_faucet(
+        &mut self,
+        owner: AccountOwner,
+        amount: Amount,
+    ) -> Result<(), TokenError> {
+        let reserve = self
+            .faucet_reserve
+            .get()
+            .try_add(amount)
+            .map_err(|_| TokenError::Overflow)?;
+        self.debit(owner, amount).await?;
+        self.faucet_reserve.set(reserve);
         Ok(())
     }
+
+    /// Pay a faucet claim out of the reserve, enforcing the faucet policy
+    pub async fn claim_faucet(
+        &mut self,
+        owner: AccountOwner,
+        current_time: Timestamp,
+    ) -> Result<Amount, TokenError> {
+        let config = self.faucet.get().clone();
+        if !config.enabled {
+            return Err(TokenError::FaucetDisabled);
+        }
+
+        let mut bonus = self.daily_bonuses.get(&owner).await?.unwrap_or_default();
+        if !bonus.can_claim(current_time, config.cooldown_seconds) {
+            return Err(TokenError::BonusNotAvailable);
+        }
+
+        let total_claimed = bonus
+            .total_claimed
+            .try_add(config.amount)
+            .map_err(|_| TokenError::Overflow)?;
+        if config.lifetime_cap.is_some_and(|cap| total_claimed > cap) {
+            return Err(TokenError::FaucetCapReached);
+        }
+
+        let distributed = self
+            .faucet_distributed
+            .get()
+            .try_add(config.amount)
+            .map_err(|_| TokenError::Overflow)?;
+        if config
+            .global_budget
+            .is_some_and(|budget| distributed > budget)
+        {
+            return Err(TokenError::FaucetBudgetExhausted);
+        }
+
+        let reserve = self
+            .faucet_reserve
+            .get()
+            .try_sub(config.amount)
+            .map_err(|_| TokenError::FaucetReserveEmpty)?;
+
+        self.credit(owner, config.amount).await?;
+        self.faucet_reserve.set(reserve);
+        self.faucet_distributed.set(distributed);
+        bonus.last_claim = current_time;
+        bonus.total_claimed = total_claimed;
+        self.daily_bonuses.insert(&owner, bonus)?;
+        Ok(config.amount)
+    }
 }